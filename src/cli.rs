@@ -1,56 +1,153 @@
-use color_eyre::owo_colors::OwoColorize;
-use std::str::FromStr;
-
-use clap::Parser;
-
-use crate::{error::ArgError, style_text};
-
-/// Todo:
-/// Try out mangaraw.ma
-#[derive(Debug, Clone, Default)]
-pub enum SupportedSites {
-    #[default]
-    MangaReader,
-    MangaGun,
-    /// https://rawmanga.net/manga/zaziyoziyoranzu-the-jojolands/di-1hua
-    RawManga,
-}
-
-/// manga_dl Url argument
-#[derive(Debug, Clone)]
-pub struct Url {
-    pub url: String,
-    pub title: Option<String>,
-    pub site: SupportedSites,
-}
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use clap::{Parser, Subcommand};
+
+use manga_dl::{
+    convert::ConvertFormat,
+    definition::{default_sites_dir, load_site_definitions},
+    error::ArgError,
+    failure_log::FAILURE_LOG_FILE,
+    naming::NameTemplate,
+    options::{ChapterRange, DownloadOptions, LogLevel, PageRanges},
+    output::OutputFormat,
+    retry::RetryPolicy,
+    shutdown::Shutdown,
+    site::{register_adapters, SiteAdapter},
+    trim::{BorderColor, TrimOptions},
+    Url,
+};
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0    every page of every url was downloaded
+  1    some pages or urls failed, the rest was downloaded
+  2    invalid arguments
+  3    nothing could be downloaded
+  130  interrupted by Ctrl-C or SIGTERM";
 
 /// arguments passed to the manga_dl cli
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = EXIT_CODES)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     #[arg(short, long, num_args = 1..)]
     pub urls: Vec<String>,
-    /// Only downloads specified from a url.
-    /// (ie. if they didn't download properly).
-    #[arg(long, num_args = 1..)]
-    pub indexes: Option<Vec<usize>>,
+    /// Only downloads these pages of each chapter, counted from 1.
+    /// (ie. 1-5,9,12- | 3); both ends are included.
+    #[arg(long)]
+    pub pages: Option<PageRanges>,
+    /// Only downloads chapters in this range from series urls.
+    /// (ie. 10..25 | 10.. | ..25 | 12); both ends are included.
+    #[arg(long)]
+    pub chapters: Option<ChapterRange>,
+    /// Names chapter folders relative to the download path with a template
+    /// (ie. {series}/{chapter:04}); placeholders: {series} {chapter} {language} {site} {title}.
+    /// Chapters are named after their url otherwise.
+    #[arg(long)]
+    pub name: Option<NameTemplate>,
+    /// Skips pages the chapter's manifest.json marks as downloaded
+    /// and only fetches the missing ones.
+    #[arg(long)]
+    pub resume: bool,
+    /// How many images to fetch at once.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    pub concurrency: u16,
+    /// How many times a page (or an element on it) is tried before giving up.
+    #[arg(long, default_value_t = 3)]
+    pub retries: u32,
+    /// Delay before the first retry in milliseconds; doubled on every following one.
+    #[arg(long, default_value_t = 500)]
+    pub retry_delay_ms: u64,
+    /// Timeout of a single image request in milliseconds.
+    #[arg(long, default_value_t = 2500)]
+    pub timeout_ms: u64,
+    /// How each chapter is saved.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Dir)]
+    pub output: OutputFormat,
+    /// Packs all chapters of a series url into a single file
+    /// instead of one per chapter (epub | pdf).
+    #[arg(long)]
+    pub bundle: bool,
+    /// Re-encodes every page to this image format.
+    /// Pages are saved in the format the site serves them in otherwise.
+    #[arg(long, value_enum)]
+    pub convert_to: Option<ConvertFormat>,
+    /// Trims the uniform borders around every page.
+    #[arg(long)]
+    pub trim_borders: bool,
+    /// Border color to trim (ie. #111111 | 17,17,17).
+    /// Detected from the corners of each page if not specified.
+    #[arg(long, requires = "trim_borders")]
+    pub trim_color: Option<BorderColor>,
+    /// How far in percent a pixel may differ from the border color and still be trimmed.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub trim_tolerance: u8,
     /// Use an absolute path to download images to.
     /// Defaults to ./download if not specified.
     #[arg(short, long)]
     pub input_path: Option<String>,
+    /// Path to the geckodriver executable.
+    /// Looked up on the PATH if not specified.
+    #[arg(long, env = "GECKODRIVER")]
+    pub geckodriver: Option<PathBuf>,
+    /// Path to the Firefox executable.
+    /// Looked up on the PATH and in the default install location if not specified.
+    #[arg(long, env = "FIREFOX_BIN")]
+    pub firefox: Option<PathBuf>,
+    /// Url of a WebDriver server that's already running (ie. http://localhost:4444).
+    /// geckodriver isn't started when this is set.
+    #[arg(long)]
+    pub webdriver: Option<String>,
+    /// Writes a json summary of the run to this file:
+    /// every url, its chapters, pages expected vs. written and why pages failed.
+    #[arg(long)]
+    pub report: Option<PathBuf>,
+    /// Every page that still fails after its retries is appended to this file,
+    /// one json object per line; pass it to `manga_dl retry` to fetch them again.
+    #[arg(long, default_value = FAILURE_LOG_FILE)]
+    pub error_log: PathBuf,
+    /// Directory of site definition files (*.toml | *.json).
+    /// Defaults to <config dir>/manga_dl/sites if it exists.
+    #[arg(long)]
+    pub sites: Option<PathBuf>,
     #[clap(value_enum, default_value_t=LogLevel::Normal)]
     pub log: LogLevel,
 }
 
-#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
-pub enum LogLevel {
-    Normal,
-    Full,
-    Verbose,
-    Quiet,
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Downloads the pages of a failure log again into their original folders.
+    ///
+    /// Entries that succeed are removed from the log.
+    /// Chapters that end up complete are packed into the --output format.
+    Retry {
+        /// The --error-log of an earlier run.
+        log: PathBuf,
+    },
 }
 
 impl Cli {
+    /// registers the site definitions so urls can be matched against them
+    fn load_sites(&self) -> Result<(), ArgError> {
+        let dir = match &self.sites {
+            Some(dir) => dir.clone(),
+            None => match default_sites_dir() {
+                Some(dir) if dir.is_dir() => dir,
+                _ => return Ok(()),
+            },
+        };
+        let sites = load_site_definitions(&dir)?;
+        register_adapters(
+            sites
+                .into_iter()
+                .map(|site| Box::new(site) as Box<dyn SiteAdapter>)
+                .collect(),
+        );
+
+        Ok(())
+    }
+
     pub fn check_urls(&self) -> Result<Vec<Url>, ArgError> {
         let mut urls = Vec::with_capacity(self.urls.len());
         for url in &self.urls {
@@ -61,129 +158,52 @@ impl Cli {
     }
 }
 
-pub fn get_args() -> Result<Cli, ArgError> {
-    let args = Cli::parse();
-    args.check_urls()?;
-
-    Ok(args)
-}
-
-impl FromStr for Url {
-    type Err = ArgError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let valid_url_str = Url::check_url(s.to_string())?;
-        let site = Url::is_site_supported(&valid_url_str)?;
-        let title = Url::get_title(&valid_url_str, &site);
-        let url = Url {
-            url: valid_url_str,
-            title,
-            site,
-        };
-
-        Ok(url)
-    }
-}
-
-impl Url {
-    fn is_site_supported(url: &str) -> Result<SupportedSites, ArgError> {
-        if url.contains("mangareader") {
-            return Ok(SupportedSites::MangaReader);
-        } else if url.contains("mangagun") {
-            return Ok(SupportedSites::MangaGun);
-        } else if url.contains("rawmanga") {
-            return Ok(SupportedSites::RawManga);
+impl Cli {
+    /// the download options the arguments describe
+    pub fn options(&self) -> DownloadOptions {
+        DownloadOptions {
+            root: self
+                .input_path
+                .clone()
+                .unwrap_or_else(|| "./download".to_string()),
+            chapters: self.chapters,
+            pages: self.pages.clone(),
+            name: self.name.clone(),
+            resume: self.resume,
+            concurrency: self.concurrency.into(),
+            retry: RetryPolicy {
+                max_attempts: self.retries.max(1),
+                base_delay: Duration::from_millis(self.retry_delay_ms),
+                ..Default::default()
+            },
+            timeout: Duration::from_millis(self.timeout_ms),
+            output: self.output,
+            bundle: self.bundle,
+            convert_to: self.convert_to,
+            trim: self.trim_borders.then_some(TrimOptions {
+                color: self.trim_color,
+                tolerance: self.trim_tolerance,
+            }),
+            error_log: Some(self.error_log.clone()),
+            geckodriver: self.geckodriver.clone(),
+            firefox: self.firefox.clone(),
+            webdriver: self.webdriver.clone(),
+            log: self.log.clone(),
+            on_progress: None,
+            shutdown: Shutdown::default(),
         }
-        Err(ArgError::WebsiteNotSupported(url.to_string()))
     }
+}
 
-    /// extract title from url based on the site
-    fn get_title(url: &str, site: &SupportedSites) -> Option<String> {
-        match site {
-            SupportedSites::MangaReader => {
-                if let Some(start) = url.split_once("/read/") {
-                    // extract the part after "/read/" until the next "/"
-                    return Some(start.1.replace("/", "-").to_string());
-                }
-            }
-            SupportedSites::MangaGun => {
-                if let Some(start) = url.rsplit_once("/") {
-                    return Some(start.1.to_string());
-                }
-            }
-            SupportedSites::RawManga => {
-                if let Some(start) = url.split_once("/manga/") {
-                    return Some(start.1.replace("/", "_").trim().to_string());
-                }
-            }
+pub fn get_args() -> Result<Cli, ArgError> {
+    let args = Cli::parse();
+    args.load_sites()?;
+    args.check_urls()?;
+    if let Some(Command::Retry { log }) = &args.command {
+        if !log.is_file() {
+            return Err(ArgError::MissingFailureLog(log.clone()));
         }
-        None
     }
 
-    fn check_url(url: String) -> Result<String, ArgError> {
-        let mut url = url;
-        if !url.starts_with("https://") {
-            let new_url = format!("https://{}", url);
-            url = new_url;
-        }
-        let url = url.to_lowercase();
-
-        if !url.is_ascii() {
-            return Err(ArgError::InvalidUrl {
-                url,
-                reason: style_text!("URL is not valid ASCII"),
-                example: style_text!("mangareader.to/read/vagabond-4/ja/chapter-6"),
-            });
-        }
-
-        if url.is_empty() {
-            return Err(ArgError::InvalidUrl {
-                url,
-                reason: style_text!("Empty string"),
-                example: style_text!("*.com | *.to"),
-            });
-        }
-
-        if !(url.contains(".to") || url.contains(".com") || url.contains(".net")) {
-            return Err(ArgError::InvalidUrl {
-                url,
-                reason: style_text!("URL is missing or has an invalid top-level domain"),
-                example: style_text!("*.com | *.to | *.net"),
-            });
-        }
-
-        if !(url.contains("mangareader.to")
-            || url.contains("mangagun.net")
-            || url.contains("rawmanga"))
-        {
-            return Err(ArgError::WebsiteNotSupported(url));
-        }
-
-        if url.contains("mangareader.to") && !url.contains("/read") {
-            return Err(ArgError::InvalidUrl {
-                url,
-                reason: style_text!("mangareader URL is missing /read"),
-                example: style_text!("mangareader.to/read/vagabond-4/ja/chapter-6"),
-            });
-        }
-
-        Ok(url)
-    }
+    Ok(args)
 }
-
-// #[cfg(test)]
-// mod url_tests {
-//     use super::*;
-//
-//     #[test]
-//     fn test_is_site_supported() -> Result<(), ArgError> {
-//         let mangareader = "https://mangareader.to/read/one-piece-3/ja/chapter-1";
-//         let mangagun = "https://mangagun.net/gunchap-999-shmg-one-piece-raw.html";
-//         let mangaraw = "https://rawmanga.net/manga/one-piece/chapter-999";
-//
-//         // let mangareader = Url::from_str(mangareader)?;
-//         // let mangagun = Url::from_str(mangagun)?;
-//         // let mangaraw = Url::from_str(mangaraw)?;
-//
-//         Ok(())
-//     }
-// }
@@ -0,0 +1,108 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    output::{escape_xml, utc_now, write_file_atomic},
+    site::parse_chapter_number,
+    url::Url,
+};
+
+/// name of the metadata file inside a chapter folder and its cbz
+pub const COMIC_INFO_FILE: &str = "ComicInfo.xml";
+
+/// The ComicInfo.xml metadata of a chapter, as read by comic readers and library managers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComicInfo {
+    pub series: String,
+    pub number: Option<f32>,
+    pub page_count: usize,
+    /// the chapter url the pages were scraped from
+    pub web: String,
+    pub language: Option<String>,
+    /// the day the chapter was scraped as (year, month, day)
+    pub date: (i64, u32, u32),
+}
+
+impl ComicInfo {
+    /// metadata of a chapter url, the series falls back to the url's title
+    pub fn new(url: &Url, page_count: usize) -> Self {
+        let (year, month, day, ..) = utc_now();
+        let series = url
+            .site
+            .series(&url.url)
+            .or_else(|| url.title.clone())
+            .unwrap_or_default();
+
+        ComicInfo {
+            series,
+            number: parse_chapter_number(&url.url),
+            page_count,
+            web: url.url.clone(),
+            language: url.site.language(&url.url),
+            date: (year, month, day),
+        }
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut fields = format!("  <Series>{}</Series>\n", escape_xml(&self.series));
+        if let Some(number) = self.number {
+            fields.push_str(&format!("  <Number>{number}</Number>\n"));
+        }
+        let (year, month, day) = self.date;
+        fields.push_str(&format!(
+            "  <Year>{year}</Year>\n  <Month>{month}</Month>\n  <Day>{day}</Day>\n"
+        ));
+        fields.push_str(&format!("  <Web>{}</Web>\n", escape_xml(&self.web)));
+        fields.push_str(&format!("  <PageCount>{}</PageCount>\n", self.page_count));
+        if let Some(language) = &self.language {
+            fields.push_str(&format!(
+                "  <LanguageISO>{}</LanguageISO>\n",
+                escape_xml(language)
+            ));
+        }
+
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+{fields}  <Manga>YesAndRightToLeft</Manga>
+</ComicInfo>
+"#
+        )
+    }
+
+    /// writes `ComicInfo.xml` into the chapter folder
+    pub fn write(&self, dl_path: &str) -> io::Result<()> {
+        write_file_atomic(&Path::new(dl_path).join(COMIC_INFO_FILE), |mut file| {
+            file.write_all(self.to_xml().as_bytes())?;
+            Ok(file)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mangareader::MANGAREADER;
+
+    #[test]
+    fn comic_info_from_url() {
+        let url = Url {
+            url: "https://mangareader.to/read/vagabond-4/ja/chapter-6".to_string(),
+            title: Some("vagabond-4-ja-chapter-6".to_string()),
+            site: &MANGAREADER,
+            series: false,
+        };
+        let info = ComicInfo::new(&url, 42);
+        assert_eq!(info.series, "vagabond-4");
+        assert_eq!(info.number, Some(6.0));
+        assert_eq!(info.language.as_deref(), Some("ja"));
+
+        let xml = info.to_xml();
+        assert!(xml.contains("<Series>vagabond-4</Series>"));
+        assert!(xml.contains("<Number>6</Number>"));
+        assert!(xml.contains("<PageCount>42</PageCount>"));
+        assert!(xml.contains("<Manga>YesAndRightToLeft</Manga>"));
+    }
+}
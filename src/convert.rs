@@ -0,0 +1,160 @@
+use std::io::Cursor;
+
+use image::{codecs::gif::GifDecoder, AnimationDecoder, DynamicImage, ImageError, ImageFormat};
+
+/// smallest width and height a page can have, anything smaller is an icon or a placeholder
+pub const MIN_PAGE_SIDE: u32 = 100;
+
+/// Image formats pages can be converted to with `--convert-to`.
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConvertFormat {
+    Jpeg,
+    Png,
+    /// lossless webp
+    Webp,
+}
+
+impl ConvertFormat {
+    fn image_format(&self) -> ImageFormat {
+        match self {
+            ConvertFormat::Jpeg => ImageFormat::Jpeg,
+            ConvertFormat::Png => ImageFormat::Png,
+            ConvertFormat::Webp => ImageFormat::WebP,
+        }
+    }
+}
+
+/// file extension matching the magic bytes of an image, `None` if they aren't recognized
+pub fn sniff_extension(bytes: &[u8]) -> Option<&'static str> {
+    let format = image::guess_format(bytes).ok()?;
+    format.extensions_str().first().copied()
+}
+
+/// checks that the bytes are a complete page image and says why they aren't:
+/// not an image at all (ie. an html error page), cut off, undecodable,
+/// too small to be a page, or an animated loading gif.
+pub fn check_page_image(bytes: &[u8]) -> Result<(), String> {
+    let format = image::guess_format(bytes).map_err(|_| {
+        let start = String::from_utf8_lossy(&bytes[..bytes.len().min(32)]).into_owned();
+        format!("not an image, starts with `{}`", start.trim())
+    })?;
+    if !has_end_marker(format, bytes) {
+        return Err(format!(
+            "the {format:?} is cut off after {} bytes",
+            bytes.len()
+        ));
+    }
+
+    let img = image::load_from_memory_with_format(bytes, format)
+        .map_err(|e| format!("the {format:?} could not be decoded: {e}"))?;
+    if img.width() < MIN_PAGE_SIDE || img.height() < MIN_PAGE_SIDE {
+        return Err(format!(
+            "{}x{} is too small for a page",
+            img.width(),
+            img.height()
+        ));
+    }
+    // pages are never animated, the loading spinners sites show in their place are
+    if format == ImageFormat::Gif && is_animated_gif(bytes) {
+        return Err("an animated gif, most likely a loading spinner".to_string());
+    }
+
+    Ok(())
+}
+
+/// whether a file of a format that marks its end has that marker,
+/// decoders fill in the missing part of some truncated images instead of failing
+fn has_end_marker(format: ImageFormat, bytes: &[u8]) -> bool {
+    let tail = &bytes[bytes.len().saturating_sub(1024)..];
+    match format {
+        ImageFormat::Jpeg => tail.windows(2).any(|w| w == [0xFF, 0xD9]),
+        ImageFormat::Png => tail.windows(4).any(|w| w == b"IEND"),
+        ImageFormat::Gif => tail.iter().rev().find(|&&b| b != 0) == Some(&0x3B),
+        ImageFormat::WebP => bytes.get(4..8).is_some_and(|size| {
+            let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+            bytes.len() >= size + 8
+        }),
+        _ => true,
+    }
+}
+
+fn is_animated_gif(bytes: &[u8]) -> bool {
+    GifDecoder::new(Cursor::new(bytes))
+        .is_ok_and(|decoder| decoder.into_frames().take(2).count() > 1)
+}
+
+/// re-encodes an image as `to`, images already in that format are returned as they are
+pub fn convert_image(bytes: Vec<u8>, to: ConvertFormat) -> Result<Vec<u8>, ImageError> {
+    if image::guess_format(&bytes).ok() == Some(to.image_format()) {
+        return Ok(bytes);
+    }
+
+    encode_image(image::load_from_memory(&bytes)?, to.image_format())
+}
+
+/// encodes an image as `format`, dropping whatever the format can't store
+pub fn encode_image(img: DynamicImage, format: ImageFormat) -> Result<Vec<u8>, ImageError> {
+    // jpeg has no alpha channel and the webp encoder only takes 8-bit rgb(a)
+    let img = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8()),
+        ImageFormat::WebP => DynamicImage::ImageRgba8(img.to_rgba8()),
+        _ => img,
+    };
+    let mut out = Cursor::new(Vec::new());
+    img.write_to(&mut out, format)?;
+
+    Ok(out.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_and_converts() -> Result<(), ImageError> {
+        let mut png = Cursor::new(Vec::new());
+        image::RgbaImage::new(4, 4).write_to(&mut png, ImageFormat::Png)?;
+        let png = png.into_inner();
+        assert_eq!(sniff_extension(&png), Some("png"));
+        assert_eq!(sniff_extension(b"<html>"), None);
+
+        let jpeg = convert_image(png.clone(), ConvertFormat::Jpeg)?;
+        assert_eq!(sniff_extension(&jpeg), Some("jpg"));
+        let webp = convert_image(png.clone(), ConvertFormat::Webp)?;
+        assert_eq!(sniff_extension(&webp), Some("webp"));
+        assert_eq!(convert_image(png.clone(), ConvertFormat::Png)?, png);
+
+        Ok(())
+    }
+
+    #[test]
+    fn page_images() -> Result<(), ImageError> {
+        let page = image::RgbImage::from_pixel(120, 180, image::Rgb([200, 10, 10]));
+        for format in [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Gif] {
+            let bytes = encode_image(DynamicImage::ImageRgb8(page.clone()), format)?;
+            assert_eq!(check_page_image(&bytes), Ok(()), "{format:?}");
+
+            let cut = &bytes[..bytes.len() / 2];
+            assert!(check_page_image(cut).is_err(), "cut {format:?}");
+        }
+
+        let reason = check_page_image(b"<!DOCTYPE html><html>404</html>").unwrap_err();
+        assert!(reason.starts_with("not an image"), "{reason}");
+
+        let icon = encode_image(DynamicImage::new_rgb8(32, 32), ImageFormat::Png)?;
+        assert!(check_page_image(&icon).unwrap_err().contains("too small"));
+
+        let mut spinner = Vec::new();
+        {
+            let mut encoder = image::codecs::gif::GifEncoder::new(&mut spinner);
+            for shade in [0, 255] {
+                let frame = image::RgbaImage::from_pixel(120, 120, image::Rgba([shade, 0, 0, 255]));
+                encoder.encode_frame(image::Frame::new(frame))?;
+            }
+        }
+        assert!(check_page_image(&spinner).unwrap_err().contains("spinner"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,438 @@
+//! Sites described by a definition file instead of a module.
+//!
+//! Definitions are loaded at startup from the `--sites` directory
+//! (defaults to `<config dir>/manga_dl/sites`); every `*.toml` and `*.json` file is one site:
+//!
+//! ```toml
+//! name = "RawMangaMirror"
+//! host = "rawmanga.mirror.net"
+//! url_pattern = "^/manga/"
+//! title_pattern = "/manga/(?<title>.+)"
+//! page_selector = "div.page-chapter img"
+//! src_attribute = "data-src"
+//! hide = [".navbar", "#adModal"]
+//! ```
+//!
+//! Readers that only show one page at a time also set `next_page_script`
+//! (ie. `"hozNextImage()"`) and `page_count_selector`.
+//! Series pages are supported by setting `series_pattern` and `chapter_selector`.
+//! Sites that serve their images in plain html can set `needs_browser = false`
+//! to be scraped without starting a browser.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use fantoccini::{Client, Locator};
+use regex::Regex;
+use reqwest::{Client as ReqClient, Url as ReqUrl};
+use scraper::Html;
+use serde::Deserialize;
+
+use crate::{
+    error::{DownloadImageError, MainError, SiteDefinitionError},
+    g_handle_popup,
+    http::{select_chapter_links, select_urls},
+    mangagun::execute_set_element_hidden_inline,
+    mangareader::download_img_src,
+    options::DownloadOptions,
+    retry::RetryPolicy,
+    site::{host_is, list_chapter_links, Chapter, Page, PageSource, Reader, SiteAdapter},
+};
+
+/// contents of a single site definition file
+#[derive(Deserialize, Debug, Clone)]
+pub struct SiteDefinition {
+    pub name: String,
+    /// domain of the site, its subdomains match too
+    pub host: String,
+    /// regex matched against the url's path
+    pub url_pattern: String,
+    /// regex matched against the url; the `title` group (or the first group) becomes the title,
+    /// an optional `series` group names the series in the chapter metadata
+    pub title_pattern: Option<String>,
+    /// css selector matching every page image
+    pub page_selector: String,
+    /// attribute of the page image holding its url
+    #[serde(default = "default_src_attribute")]
+    pub src_attribute: String,
+    /// script that moves a one-page-at-a-time reader to the next page
+    pub next_page_script: Option<String>,
+    /// css selector of the element holding the total page count;
+    /// required with `next_page_script`, and only used with it
+    pub page_count_selector: Option<String>,
+    /// css selectors of elements to hide before reading the pages
+    #[serde(default)]
+    pub hide: Vec<String>,
+    /// regex matched against the url; matching urls are series pages
+    pub series_pattern: Option<String>,
+    /// css selector matching every chapter link of a series page
+    pub chapter_selector: Option<String>,
+    /// ISO 639-1 code of the language the site's chapters are in
+    pub language: Option<String>,
+    /// `false` scrapes the html served for each url instead of rendering it in the browser
+    #[serde(default = "default_needs_browser")]
+    pub needs_browser: bool,
+}
+
+fn default_src_attribute() -> String {
+    "src".to_string()
+}
+
+fn default_needs_browser() -> bool {
+    true
+}
+
+#[derive(Debug)]
+pub struct DeclarativeSite {
+    /// leaked so it can be returned from [`SiteAdapter::name`]
+    name: &'static str,
+    def: SiteDefinition,
+    url_re: Regex,
+    title_re: Option<Regex>,
+    series_re: Option<Regex>,
+}
+
+impl DeclarativeSite {
+    pub fn new(def: SiteDefinition, path: &Path) -> Result<Self, SiteDefinitionError> {
+        let invalid = |reason: String| SiteDefinitionError::Invalid {
+            path: path.to_path_buf(),
+            reason,
+        };
+
+        let url_re =
+            Regex::new(&def.url_pattern).map_err(|e| invalid(format!("url_pattern: {e}")))?;
+        let title_re = def
+            .title_pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| invalid(format!("title_pattern: {e}")))?;
+        let series_re = def
+            .series_pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| invalid(format!("series_pattern: {e}")))?;
+
+        if def.next_page_script.is_some() && def.page_count_selector.is_none() {
+            return Err(invalid(
+                "next_page_script requires a page_count_selector".to_string(),
+            ));
+        }
+        if def.page_count_selector.is_some() && def.next_page_script.is_none() {
+            return Err(invalid(
+                "page_count_selector requires a next_page_script".to_string(),
+            ));
+        }
+        if def.next_page_script.is_some() && !def.needs_browser {
+            return Err(invalid(
+                "next_page_script requires needs_browser".to_string(),
+            ));
+        }
+        if def.series_pattern.is_some() && def.chapter_selector.is_none() {
+            return Err(invalid(
+                "series_pattern requires a chapter_selector".to_string(),
+            ));
+        }
+
+        Ok(DeclarativeSite {
+            name: Box::leak(def.name.clone().into_boxed_str()),
+            def,
+            url_re,
+            title_re,
+            series_re,
+        })
+    }
+
+    fn chapter_selector(&self) -> Result<&str> {
+        self.def
+            .chapter_selector
+            .as_deref()
+            .ok_or_else(|| eyre!("{} has no chapter_selector", self.name))
+    }
+
+    async fn count_pages(
+        &self,
+        client: &Client,
+        selector: &str,
+        policy: &RetryPolicy,
+    ) -> Result<usize> {
+        let elm = policy
+            .run(|| {
+                client
+                    .wait()
+                    .at_most(Duration::from_millis(1000))
+                    .for_element(Locator::Css(selector))
+            })
+            .await
+            .wrap_err(format!("total pages element not found: {selector}"))?;
+        let text = elm.text().await?;
+
+        text.trim()
+            .parse::<usize>()
+            .map_err(|_| MainError::ParseCounterElement(text).into())
+    }
+}
+
+#[async_trait]
+impl SiteAdapter for DeclarativeSite {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn detect(&self, url: &ReqUrl) -> bool {
+        host_is(url, &self.def.host) && self.url_re.is_match(url.path())
+    }
+
+    fn title(&self, url: &str) -> Option<String> {
+        let caps = self.title_re.as_ref()?.captures(url)?;
+        let title = caps.name("title").or_else(|| caps.get(1))?;
+
+        Some(title.as_str().trim_matches('/').replace("/", "-"))
+    }
+
+    fn is_series(&self, url: &str) -> bool {
+        self.series_re.as_ref().is_some_and(|re| re.is_match(url))
+    }
+
+    fn series(&self, url: &str) -> Option<String> {
+        let caps = self.title_re.as_ref()?.captures(url)?;
+        Some(caps.name("series")?.as_str().trim_matches('/').to_string())
+    }
+
+    fn language(&self, _url: &str) -> Option<String> {
+        self.def.language.clone()
+    }
+
+    fn needs_browser(&self) -> bool {
+        self.def.needs_browser
+    }
+
+    async fn list_chapters(&self, client: &Client) -> Result<Vec<Chapter>> {
+        let selector = self.chapter_selector()?;
+        for selector in &self.def.hide {
+            execute_set_element_hidden_inline(client, selector).await?;
+        }
+        list_chapter_links(client, selector).await
+    }
+
+    async fn list_pages(
+        &self,
+        reader: &Reader<'_>,
+        options: &DownloadOptions,
+    ) -> Result<Vec<Page>> {
+        let client = reader.client;
+        for selector in &self.def.hide {
+            execute_set_element_hidden_inline(client, selector).await?;
+        }
+        g_handle_popup(client).await.wrap_err(line!())?;
+
+        if let Some(selector) = &self.def.page_count_selector {
+            let count = self.count_pages(client, selector, &options.retry).await?;
+            let pages = (0..count)
+                .map(|i| Page {
+                    index: i,
+                    source: PageSource::Browser,
+                })
+                .collect();
+            return Ok(pages);
+        }
+
+        let imgs = client
+            .find_all(Locator::Css(&self.def.page_selector))
+            .await?;
+        // attributes hold the raw value, which may be relative to the chapter
+        let base = client.current_url().await?;
+        let mut pages = Vec::with_capacity(imgs.len());
+        for (i, img) in imgs.into_iter().enumerate() {
+            let src = img.attr(&self.def.src_attribute).await?;
+            if let Some(src) = src.and_then(|src| base.join(src.trim()).ok()) {
+                pages.push(Page {
+                    index: i,
+                    source: PageSource::Src(src.to_string()),
+                });
+            }
+        }
+
+        Ok(pages)
+    }
+
+    fn parse_chapters(&self, html: &Html, url: &str) -> Result<Vec<Chapter>> {
+        select_chapter_links(html, self.chapter_selector()?, url)
+    }
+
+    fn parse_pages(&self, html: &Html, url: &str) -> Result<Vec<Page>> {
+        let srcs = select_urls(html, &self.def.page_selector, &self.def.src_attribute, url)?;
+        let pages = srcs
+            .into_iter()
+            .enumerate()
+            .map(|(i, src)| Page {
+                index: i,
+                source: PageSource::Src(src),
+            })
+            .collect();
+
+        Ok(pages)
+    }
+
+    async fn fetch_page(
+        &self,
+        reader: &Reader<'_>,
+        req_client: &ReqClient,
+        page: &Page,
+    ) -> Result<Vec<u8>, DownloadImageError> {
+        let client = reader.client;
+        if let Some(script) = &self.def.next_page_script {
+            while reader.cursor.load(Ordering::Relaxed) < page.index {
+                client.execute(script, vec![]).await?;
+                reader.cursor.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let img = client
+            .wait()
+            .at_most(Duration::from_millis(2000))
+            .for_element(Locator::Css(&self.def.page_selector))
+            .await
+            .map_err(|_| DownloadImageError::MissingImgElement(page.number().to_string()))?;
+        let src = img
+            .attr(&self.def.src_attribute)
+            .await?
+            .ok_or_else(|| DownloadImageError::MissingImgElement(page.number().to_string()))?;
+        let src = client
+            .current_url()
+            .await?
+            .join(src.trim())
+            .map_err(|e| eyre!("invalid src {src}: {e}"))?;
+
+        download_img_src(src.as_str(), req_client).await
+    }
+}
+
+/// default directory site definitions are loaded from
+pub fn default_sites_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let config = std::env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(not(target_os = "windows"))]
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    config.map(|dir| dir.join("manga_dl").join("sites"))
+}
+
+/// parses every `*.toml` and `*.json` file in `dir`, sorted by file name
+pub fn load_site_definitions(dir: &Path) -> Result<Vec<DeclarativeSite>, SiteDefinitionError> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            matches!(
+                p.extension().and_then(|ext| ext.to_str()),
+                Some("toml" | "json")
+            )
+        })
+        .collect();
+    paths.sort();
+
+    let mut sites = Vec::with_capacity(paths.len());
+    for path in paths {
+        let text = fs::read_to_string(&path)?;
+        let def = parse_site_definition(&text, &path)?;
+        sites.push(DeclarativeSite::new(def, &path)?);
+    }
+
+    Ok(sites)
+}
+
+fn parse_site_definition(text: &str, path: &Path) -> Result<SiteDefinition, SiteDefinitionError> {
+    let def = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(text).map_err(|e| SiteDefinitionError::Json {
+            path: path.to_path_buf(),
+            source: e,
+        })?,
+        _ => toml::from_str(text).map_err(|e| SiteDefinitionError::Toml {
+            path: path.to_path_buf(),
+            source: e,
+        })?,
+    };
+
+    Ok(def)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_definition() -> Result<(), SiteDefinitionError> {
+        let path = Path::new("mirror.toml");
+        let text = r#"
+            name = "Mirror"
+            host = "mirror.net"
+            url_pattern = "^/manga/"
+            title_pattern = "/manga/(?<title>.+)"
+            page_selector = "div.page-chapter img"
+            hide = [".navbar"]
+        "#;
+        let site = DeclarativeSite::new(parse_site_definition(text, path)?, path)?;
+
+        assert_eq!(site.name(), "Mirror");
+        assert_eq!(site.def.src_attribute, "src");
+        let url = |s: &str| ReqUrl::parse(s).unwrap();
+        assert!(site.detect(&url("https://mirror.net/manga/one-piece/chapter-1")));
+        assert!(site.detect(&url("https://www.mirror.net/manga/one-piece/chapter-1")));
+        assert!(!site.detect(&url("https://rawmanga.net/manga/one-piece/chapter-1")));
+        assert!(!site.detect(&url("https://example.com/?q=mirror.net/manga/x")));
+        assert!(!site.detect(&url("https://notmirror.net/manga/one-piece/chapter-1")));
+        assert_eq!(
+            site.title("https://mirror.net/manga/one-piece/chapter-1")
+                .as_deref(),
+            Some("one-piece-chapter-1")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn next_page_script_requires_count() {
+        let path = Path::new("reader.json");
+        let text = r#"{
+            "name": "Reader",
+            "host": "reader.to",
+            "url_pattern": "^/read/",
+            "page_selector": "div.active img",
+            "next_page_script": "next()"
+        }"#;
+        let def = parse_site_definition(text, path).unwrap();
+
+        assert!(matches!(
+            DeclarativeSite::new(def, path),
+            Err(SiteDefinitionError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn page_count_requires_next_page_script() {
+        let path = Path::new("reader.json");
+        let text = r#"{
+            "name": "Reader",
+            "host": "reader.to",
+            "url_pattern": "^/read/",
+            "page_selector": "div.page img",
+            "page_count_selector": "span.total"
+        }"#;
+        let def = parse_site_definition(text, path).unwrap();
+
+        let Err(SiteDefinitionError::Invalid { reason, .. }) = DeclarativeSite::new(def, path)
+        else {
+            panic!("a page_count_selector without next_page_script was accepted");
+        };
+        assert_eq!(reason, "page_count_selector requires a next_page_script");
+    }
+}
@@ -0,0 +1,723 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    eyre::{eyre, Result, WrapErr},
+    owo_colors::OwoColorize,
+};
+use futures_util::{future, stream, StreamExt};
+use reqwest::Client as ReqClient;
+use scraper::Html;
+use spinners::Spinner;
+
+use crate::{
+    comicinfo::ComicInfo,
+    convert::{check_page_image, convert_image, sniff_extension, ConvertFormat},
+    driver::BrowserSession,
+    epub::write_epub,
+    error::DownloadImageError,
+    failure_log::{self, FailedPage},
+    http::fetch_html,
+    loading::{
+        downloading_panel_data_msg, print_not_packed_msg, print_packed_msg, print_reqerr_count,
+        print_resume_msg, spinner,
+    },
+    mangareader::download_img_src,
+    manifest::Manifest,
+    naming::folder_name,
+    options::{DownloadOptions, LogLevel, ProgressEvent},
+    output::{packed_path, write_cbz, write_page, ChapterPages, OutputFormat},
+    pdf::write_pdf,
+    report::{ChapterReport, PageFailure},
+    retry::RetryError,
+    setup_nav,
+    site::{Chapter, Page, PageSource, Reader, SiteAdapter},
+    style_text,
+    trim::TrimOptions,
+    url::Url,
+};
+
+/// downloads every chapter of a series url (or the ones in `--chapters`)
+/// into per-chapter folders inside `<parent>/<series title>`.
+///
+/// every chapter that was tried is added to `reports`, even when the series fails.
+pub async fn dl_series(
+    browser: Option<&BrowserSession>,
+    req_client: &ReqClient,
+    url: &Url,
+    options: &DownloadOptions,
+    parent: &str,
+    reports: &mut Vec<ChapterReport>,
+) -> Result<()> {
+    let (title, dl_path) =
+        setup_nav(browser.map(BrowserSession::client), url, parent, options).await?;
+
+    let mut chapters = list_chapters(browser, req_client, url).await?;
+    if let Some(range) = &options.chapters {
+        chapters.retain(|chapter| range.contains(chapter.number));
+    }
+    if chapters.is_empty() {
+        return Err(eyre!("no chapters to download from: {}", url.url));
+    }
+    if !options.log.is_quiet() {
+        println!(
+            "{}: {} chapter(s)",
+            style_text!(&title, bold),
+            style_text!(chapters.len(), url)
+        );
+    }
+    options.emit(ProgressEvent::SeriesListed {
+        url: url.url.clone(),
+        chapters: chapters.len(),
+    });
+
+    // with --bundle the chapters stay loose until the whole series is packed at the end
+    let bundle = options.bundle && options.output.bundles();
+    let mut finished = Vec::new();
+    let mut failed = Vec::new();
+    for chapter in chapters {
+        if options.shutdown.requested() {
+            break;
+        }
+        let chapter_url = Url {
+            url: chapter.url.clone(),
+            title: Some(chapter.dir_name()),
+            site: url.site,
+            series: false,
+        };
+        // templated names are relative to the root, not the series folder
+        let (chapter_url, chapter_parent) = match &options.name {
+            Some(template) => (
+                template.apply(&chapter_url, Some(&title), Some(chapter.number)),
+                parent,
+            ),
+            None => (chapter_url, dl_path.as_str()),
+        };
+        let res = match bundle {
+            true => fetch_chapter(
+                browser,
+                req_client,
+                &chapter_url,
+                options,
+                chapter_parent,
+                None,
+            )
+            .await
+            .map(|download| {
+                reports.push(download.report());
+                finished.push(download);
+            }),
+            false => {
+                dl_chapter(
+                    browser,
+                    req_client,
+                    &chapter_url,
+                    options,
+                    chapter_parent,
+                    reports,
+                )
+                .await
+            }
+        };
+        if let Err(e) = res {
+            failed.push(format!("{}: {:?}", chapter.url, e));
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(eyre!(
+            "{} chapter(s) of {title} failed:\n{}",
+            failed.len(),
+            failed.join("\n")
+        ));
+    }
+
+    if options.shutdown.requested() {
+        return Err(eyre!(
+            "stopped before every chapter of {title} was downloaded"
+        ));
+    }
+    if bundle {
+        pack_series(&title, &dl_path, &finished, options)?;
+    }
+
+    Ok(())
+}
+
+/// a chapter folder after every page was tried
+pub struct ChapterDownload {
+    pub title: String,
+    pub dl_path: String,
+    pub manifest: Manifest,
+    pub failures: Vec<PageFailure>,
+    /// indexes of the pages that were asked for, every page of the chapter without `--pages`
+    pub selected: Vec<usize>,
+}
+
+impl ChapterDownload {
+    /// whether every selected page was written; a chapter without pages never is
+    pub fn is_complete(&self) -> bool {
+        !self.selected.is_empty() && self.written() >= self.selected.len()
+    }
+
+    /// how many of the selected pages are written
+    fn written(&self) -> usize {
+        self.selected
+            .iter()
+            .filter(|&&index| self.manifest.is_marked_done(index))
+            .count()
+    }
+
+    pub fn pages(&self) -> ChapterPages {
+        ChapterPages::from_manifest(&self.title, &self.dl_path, &self.manifest)
+    }
+
+    pub fn report(&self) -> ChapterReport {
+        ChapterReport {
+            url: self.manifest.source_url.clone(),
+            title: self.title.clone(),
+            pages_expected: self.selected.len(),
+            pages_written: self.written(),
+            failures: self.failures.clone(),
+        }
+    }
+}
+
+/// downloads a chapter url and packs it into the `--output` format,
+/// adding the chapter to `reports` once its pages were tried
+pub async fn dl_chapter(
+    browser: Option<&BrowserSession>,
+    req_client: &ReqClient,
+    url: &Url,
+    options: &DownloadOptions,
+    parent: &str,
+    reports: &mut Vec<ChapterReport>,
+) -> Result<()> {
+    if options.resume {
+        let packed = packed_path(&format!("{parent}/{}", folder_name(url)), options.output);
+        if let Some(packed) = packed.filter(|p| p.exists()) {
+            if !options.log.is_quiet() {
+                print_packed_msg(&packed);
+            }
+            return Ok(());
+        }
+    }
+
+    let download = fetch_chapter(browser, req_client, url, options, parent, None).await?;
+    reports.push(download.report());
+    pack_chapter(&download, options)
+}
+
+/// downloads the failed pages of a chapter again into the folder they were meant for,
+/// then packs the chapter if it's complete.
+///
+/// returns the entries that still failed, with their latest error.
+pub async fn retry_chapter(
+    browser: Option<&BrowserSession>,
+    req_client: &ReqClient,
+    url: &Url,
+    options: &DownloadOptions,
+    failed: Vec<FailedPage>,
+    reports: &mut Vec<ChapterReport>,
+) -> Result<Vec<FailedPage>> {
+    let dir = PathBuf::from(&failed[0].dir);
+    let parent = dir
+        .parent()
+        .map_or_else(|| ".".to_string(), |p| p.display().to_string());
+    let url = Url {
+        title: dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned()),
+        ..url.clone()
+    };
+    let indexes: Vec<usize> = failed.iter().map(|page| page.index).collect();
+
+    let download =
+        fetch_chapter(browser, req_client, &url, options, &parent, Some(&indexes)).await?;
+    reports.push(download.report());
+    let remaining = failed
+        .into_iter()
+        .filter(|page| !download.manifest.is_done(page.index, &download.dl_path))
+        .map(
+            |page| match download.failures.iter().find(|f| f.index == page.index) {
+                Some(failure) => FailedPage {
+                    source: failure.source.clone(),
+                    variant: failure.variant.to_string(),
+                    error: failure.error.clone(),
+                    attempts: failure.attempts,
+                    ..page
+                },
+                None => page,
+            },
+        )
+        .collect();
+    pack_chapter(&download, options)?;
+
+    Ok(remaining)
+}
+
+/// downloads every page of a chapter url through its [`SiteAdapter`]
+/// into `<parent>/<title>`.
+///
+/// `retry` limits the download to these page indexes and keeps the pages
+/// the chapter's manifest already has, as `manga_dl retry` does.
+pub async fn fetch_chapter(
+    browser: Option<&BrowserSession>,
+    req_client: &ReqClient,
+    url: &Url,
+    options: &DownloadOptions,
+    parent: &str,
+    retry: Option<&[usize]>,
+) -> Result<ChapterDownload> {
+    let (title, dl_path) =
+        setup_nav(browser.map(BrowserSession::client), url, parent, options).await?;
+    let site: &dyn SiteAdapter = url.site;
+    let reader = browser.map(BrowserSession::reader);
+    let reader = reader.as_ref();
+
+    let mut pages = list_pages(reader, req_client, url, options).await?;
+    let max = pages.len();
+    // most likely the site changed and its selectors match nothing anymore
+    if max == 0 {
+        return Err(eyre!("no pages found on {}", url.url));
+    }
+    fs::create_dir_all(&dl_path).wrap_err_with(|| format!("could not create {dl_path}"))?;
+    match retry {
+        Some(indexes) => pages.retain(|page| indexes.contains(&page.index)),
+        // if pages are specified, skip the ones that aren't
+        None => {
+            if let Some(numbers) = &options.pages {
+                pages.retain(|page| numbers.contains(page.number()));
+            }
+        }
+    }
+    // a retry completes the whole chapter, not only the pages it fetches again
+    let selected: Vec<usize> = match retry {
+        Some(_) => (0..max).collect(),
+        None => pages.iter().map(|page| page.index).collect(),
+    };
+
+    // pages are written as they land, the manifest tracks which ones made it
+    let resume = options.resume || retry.is_some();
+    let mut manifest = match resume {
+        true => Manifest::load(&dl_path)?.filter(|m| m.source_url == url.url),
+        false => None,
+    }
+    .unwrap_or_else(|| Manifest::new(&url.url, max));
+    manifest.expected_pages = max;
+    if resume {
+        let before = pages.len();
+        pages.retain(|page| !manifest.is_done(page.index, &dl_path));
+        if !options.log.is_quiet() {
+            print_resume_msg(before - pages.len(), before);
+        }
+    }
+    options.emit(ProgressEvent::ChapterStarted {
+        url: url.url.clone(),
+        title: title.clone(),
+        pages: pages.len(),
+    });
+
+    let policy = options.retry;
+    let dir = PathBuf::from(&dl_path);
+    let dir = dir.as_path();
+    let mut progress = ChapterProgress {
+        site,
+        url: &url.url,
+        // a retry rewrites the log itself once it knows what still fails
+        error_log: options.error_log.as_deref().filter(|_| retry.is_none()),
+        options,
+        dl_path,
+        manifest,
+        errors: Vec::new(),
+        failures: Vec::new(),
+        fetched: Vec::new(),
+        done: 0,
+        max,
+    };
+
+    // browser pages depend on the state of the page, so they're fetched one by one in order
+    let (browser_pages, src_pages): (Vec<Page>, Vec<Page>) = pages
+        .into_iter()
+        .partition(|page| page.source == PageSource::Browser);
+    let mut browser_errors = 0;
+    // only started once a page is fetched, a chapter with nothing left to fetch has none
+    let mut sp: Option<Spinner> = None;
+
+    let mut browser_pages = browser_pages.into_iter();
+    for page in browser_pages.by_ref() {
+        if options.shutdown.requested() {
+            break;
+        }
+        log_page(&page, &options.log);
+        sp = spinner(&options.log, progress.msg());
+
+        let res = policy
+            .run(|| fetch_page(site, reader, req_client, &page, options.convert_to, dir))
+            .await;
+        match res.is_err() {
+            true => browser_errors += 1,
+            false => browser_errors = 0,
+        }
+        progress.record(&page, res);
+
+        // the browser can't recover once a reader is stuck,
+        // so stop after a few pages in a row ran out of retries
+        if browser_errors > 3 {
+            break;
+        }
+    }
+    // recorded as failed so the report and `retry` know about them
+    if browser_errors > 3 {
+        for page in browser_pages {
+            let error = DownloadImageError::ReaderStuck(browser_errors);
+            progress.record(&page, Err(RetryError { error, attempts: 0 }));
+        }
+    }
+
+    // src pages only need reqwest, so fetch up to `--concurrency` at once.
+    // on shutdown no new page is started, but the ones in flight are still recorded
+    let mut fetches = stream::iter(src_pages)
+        .take_while(|_| future::ready(!options.shutdown.requested()))
+        .map(|page| async move {
+            log_page(&page, &options.log);
+            let res = policy
+                .run(|| fetch_page(site, reader, req_client, &page, options.convert_to, dir))
+                .await;
+            (page, res)
+        })
+        .buffer_unordered(options.concurrency);
+
+    while let Some((page, res)) = fetches.next().await {
+        sp = spinner(&options.log, progress.msg());
+        progress.record(&page, res);
+    }
+    if let Some(mut sp) = sp {
+        sp.stop_with_newline();
+    }
+    progress.forget_written();
+
+    if let Some(trim) = options.trim {
+        progress.trim_borders(trim).await?;
+    }
+
+    if !progress.errors.is_empty() && !options.log.is_quiet() {
+        print_reqerr_count(progress.errors.len(), &title);
+        println!("{}", "STDERROR:\n".bright_red());
+        for e in progress.errors.iter() {
+            eprintln!("{}", e.red());
+        }
+    }
+
+    let download = ChapterDownload {
+        title,
+        dl_path: progress.dl_path,
+        manifest: progress.manifest,
+        failures: progress.failures,
+        selected,
+    };
+    // the pages packed with it, fewer than the chapter has with `--pages` or failed pages
+    ComicInfo::new(url, download.pages().files.len()).write(&download.dl_path)?;
+    options.emit(ProgressEvent::ChapterFinished(download.report()));
+
+    Ok(download)
+}
+
+/// turns a finished chapter folder into the `--output` format.
+///
+/// incomplete chapters stay loose so they can be resumed.
+fn pack_chapter(download: &ChapterDownload, options: &DownloadOptions) -> Result<()> {
+    let Some(path) = packed_path(&download.dl_path, options.output) else {
+        return Ok(());
+    };
+    let quiet = options.log.is_quiet();
+    if !download.is_complete() {
+        if !quiet {
+            print_not_packed_msg(&download.dl_path);
+        }
+        return Ok(());
+    }
+
+    let pages = download.pages();
+    match options.output {
+        OutputFormat::Dir => unreachable!("folders aren't packed"),
+        OutputFormat::Cbz => write_cbz(&pages, &path)?,
+        OutputFormat::Epub => write_epub(&path, &download.title, &[pages])?,
+        OutputFormat::Pdf => write_pdf(&path, &download.title, &[pages])?,
+    }
+    fs::remove_dir_all(&download.dl_path)?;
+    if !quiet {
+        print_packed_msg(&path);
+    }
+
+    Ok(())
+}
+
+/// packs every chapter of a series into one `<series>.<format>` file
+fn pack_series(
+    title: &str,
+    dl_path: &str,
+    chapters: &[ChapterDownload],
+    options: &DownloadOptions,
+) -> Result<()> {
+    let Some(path) = packed_path(dl_path, options.output) else {
+        return Ok(());
+    };
+    let quiet = options.log.is_quiet();
+    if let Some(incomplete) = chapters.iter().find(|c| !c.is_complete()) {
+        if !quiet {
+            print_not_packed_msg(&incomplete.dl_path);
+        }
+        return Ok(());
+    }
+
+    let pages: Vec<ChapterPages> = chapters.iter().map(ChapterDownload::pages).collect();
+    match options.output {
+        OutputFormat::Epub => write_epub(&path, title, &pages)?,
+        OutputFormat::Pdf => write_pdf(&path, title, &pages)?,
+        _ => unreachable!("only bundling formats are packed per series"),
+    }
+    for chapter in chapters {
+        fs::remove_dir_all(&chapter.dl_path)?;
+    }
+    // templated chapter folders may live outside of it, so it's only removed once it's empty
+    let _ = fs::remove_dir(dl_path);
+    if !quiet {
+        print_packed_msg(&path);
+    }
+
+    Ok(())
+}
+
+/// the browser of a site that needs one
+fn require_browser<T>(browser: Option<T>) -> Result<T> {
+    browser.ok_or_else(|| eyre!("the browser wasn't started"))
+}
+
+/// lists the chapters of a series url in the browser, or from its html
+async fn list_chapters(
+    browser: Option<&BrowserSession>,
+    req_client: &ReqClient,
+    url: &Url,
+) -> Result<Vec<Chapter>> {
+    match url.site.needs_browser() {
+        true => {
+            url.site
+                .list_chapters(require_browser(browser)?.client())
+                .await
+        }
+        false => {
+            let html = fetch_html(req_client, &url.url).await?;
+            url.site
+                .parse_chapters(&Html::parse_document(&html), &url.url)
+        }
+    }
+}
+
+/// lists the pages of a chapter url in the browser, or from its html
+async fn list_pages(
+    reader: Option<&Reader<'_>>,
+    req_client: &ReqClient,
+    url: &Url,
+    options: &DownloadOptions,
+) -> Result<Vec<Page>> {
+    match url.site.needs_browser() {
+        true => url.site.list_pages(require_browser(reader)?, options).await,
+        false => {
+            let html = fetch_html(req_client, &url.url).await?;
+            url.site.parse_pages(&Html::parse_document(&html), &url.url)
+        }
+    }
+}
+
+/// fetches a page through its site, checks that it's a complete image,
+/// re-encodes it if `--convert-to` is set
+/// and writes it into `dir` right away.
+///
+/// returns the page's file name and its bytes for the manifest.
+async fn fetch_page(
+    site: &dyn SiteAdapter,
+    reader: Option<&Reader<'_>>,
+    req_client: &ReqClient,
+    page: &Page,
+    convert_to: Option<ConvertFormat>,
+    dir: &Path,
+) -> Result<(String, Vec<u8>), DownloadImageError> {
+    let bytes = match &page.source {
+        PageSource::Src(src) => download_img_src(src, req_client).await?,
+        PageSource::Browser => {
+            site.fetch_page(require_browser(reader)?, req_client, page)
+                .await?
+        }
+    };
+    check_page_image(&bytes).map_err(DownloadImageError::InvalidImage)?;
+    let bytes = match convert_to {
+        Some(format) => convert_image(bytes, format)?,
+        None => bytes,
+    };
+
+    // unrecognized bytes keep the old default rather than getting no extension
+    let ext = sniff_extension(&bytes).unwrap_or("jpg");
+    let file = format!("{}.{ext}", page.number());
+    write_page(&dir.join(&file), &bytes)?;
+
+    Ok((file, bytes))
+}
+
+fn log_page(page: &Page, log: &LogLevel) {
+    match log {
+        LogLevel::Full | LogLevel::Verbose => println!("{:?}", page),
+        _ => {}
+    }
+}
+
+/// state of a chapter download, updated as each page finishes
+struct ChapterProgress<'a> {
+    site: &'a dyn SiteAdapter,
+    url: &'a str,
+    /// where pages that ran out of attempts are appended
+    error_log: Option<&'a Path>,
+    options: &'a DownloadOptions,
+    dl_path: String,
+    manifest: Manifest,
+    errors: Vec<color_eyre::Report>,
+    failures: Vec<PageFailure>,
+    /// indexes of the pages written during this run
+    fetched: Vec<usize>,
+    done: usize,
+    max: usize,
+}
+
+impl ChapterProgress<'_> {
+    /// marks a written page as done in the manifest or collects its error, then saves the manifest.
+    ///
+    /// failing to log or save is collected too, the remaining pages are still downloaded
+    fn record(
+        &mut self,
+        page: &Page,
+        res: Result<(String, Vec<u8>), RetryError<DownloadImageError>>,
+    ) {
+        match res {
+            Ok((file, bytes)) => {
+                let path = Path::new(&self.dl_path).join(&file);
+                self.manifest.mark_done(page.index, file, &bytes);
+                self.fetched.push(page.index);
+                self.options.emit(ProgressEvent::PageWritten {
+                    url: self.url.to_string(),
+                    index: page.index,
+                    path,
+                });
+            }
+            Err(e) => {
+                let source = match &page.source {
+                    PageSource::Src(src) => src.as_str(),
+                    PageSource::Browser => self.site.name(),
+                };
+                let report = eyre!(
+                    "failed on `{}. {}` after {} attempt(s): \n{}",
+                    page.number(),
+                    source,
+                    e.attempts,
+                    e.error
+                );
+                let failure = PageFailure {
+                    index: page.index,
+                    source: source.to_string(),
+                    variant: e.error.variant(),
+                    error: e.error.to_string(),
+                    attempts: e.attempts,
+                };
+                if let Some(log) = self.error_log {
+                    if let Err(e) = failure_log::append(log, &self.failed_page(&failure)) {
+                        self.errors
+                            .push(eyre!("failed to append to `{}`: {e}", log.display()));
+                    }
+                }
+                self.options.emit(ProgressEvent::PageFailed {
+                    url: self.url.to_string(),
+                    index: page.index,
+                    error: failure.error.clone(),
+                });
+                self.failures.push(failure);
+                self.manifest.mark_failed(page.index, e.to_string());
+                self.errors.push(report);
+            }
+        }
+        self.done += 1;
+        if let Err(e) = self.manifest.save(&self.dl_path) {
+            self.errors.push(eyre!(
+                "failed to save the manifest of `{}`: {e}",
+                self.dl_path
+            ));
+        }
+    }
+
+    /// removes the pages written during this run from the failure log,
+    /// they may have failed in an earlier one
+    fn forget_written(&mut self) {
+        let Some(log) = self.error_log else {
+            return;
+        };
+        if self.fetched.is_empty() {
+            return;
+        }
+        if let Err(e) = failure_log::remove(log, self.url, &self.log_dir(), &self.fetched) {
+            self.errors
+                .push(eyre!("failed to update `{}`: {e}", log.display()));
+        }
+    }
+
+    /// the chapter folder as written to the failure log;
+    /// absolute so `manga_dl retry` finds the folder from any working directory
+    fn log_dir(&self) -> String {
+        fs::canonicalize(&self.dl_path)
+            .map_or_else(|_| self.dl_path.clone(), |p| p.display().to_string())
+    }
+
+    /// the failure log entry of a page
+    fn failed_page(&self, failure: &PageFailure) -> FailedPage {
+        FailedPage {
+            url: self.url.to_string(),
+            site: self.site.name().to_string(),
+            dir: self.log_dir(),
+            index: failure.index,
+            source: failure.source.clone(),
+            variant: failure.variant.to_string(),
+            error: failure.error.clone(),
+            attempts: failure.attempts,
+        }
+    }
+
+    /// trims the pages written during this run and updates their hashes,
+    /// resumed pages were trimmed by the run that wrote them
+    async fn trim_borders(&mut self, options: TrimOptions) -> Result<()> {
+        let pages: Vec<(usize, String)> = self
+            .fetched
+            .iter()
+            .filter_map(|&index| Some((index, self.manifest.pages.get(&index)?.file.clone())))
+            .collect();
+        let paths = pages
+            .iter()
+            .map(|(_, file)| Path::new(&self.dl_path).join(file))
+            .collect();
+
+        let trimmed = tokio::task::spawn_blocking(move || options.trim_pages(paths)).await?;
+        for ((index, file), (path, res)) in pages.into_iter().zip(trimmed) {
+            match res {
+                Ok(Some(bytes)) => self.manifest.mark_done(index, file, &bytes),
+                Ok(None) => {}
+                Err(e) => self
+                    .errors
+                    .push(eyre!("failed to trim `{}`: {e}", path.display())),
+            }
+        }
+
+        Ok(self.manifest.save(&self.dl_path)?)
+    }
+
+    fn msg(&self) -> String {
+        downloading_panel_data_msg(self.done as u16, self.max as u16)
+    }
+}
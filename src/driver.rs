@@ -0,0 +1,418 @@
+//! Finding and starting the geckodriver and Firefox installed on the machine.
+//!
+//! Both are looked up from `--geckodriver`/`--firefox` (or `$GECKODRIVER`/`$FIREFOX_BIN`),
+//! then on the PATH; Firefox is also looked for where its installers put it.
+use std::{
+    env, fs,
+    net::{Ipv4Addr, TcpListener},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::OnceLock,
+    time::{Duration, Instant, SystemTime},
+};
+
+use fantoccini::Client;
+use regex::Regex;
+use serde_json::json;
+use tokio::{
+    net::TcpStream,
+    runtime::{Handle, RuntimeFlavor},
+    task,
+    time::{sleep, timeout},
+};
+
+use crate::{
+    error::DriverError,
+    options::{DownloadOptions, LogLevel},
+    site::{Reader, SessionState},
+};
+
+const GECKODRIVER_RELEASES: &str = "https://github.com/mozilla/geckodriver/releases";
+
+/// The geckodriver and Firefox executables a run uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Driver {
+    pub geckodriver: PathBuf,
+    pub firefox: PathBuf,
+}
+
+impl Driver {
+    /// finds geckodriver and firefox and checks that their versions work together
+    pub fn locate(options: &DownloadOptions) -> Result<Self, DriverError> {
+        let geckodriver = match &options.geckodriver {
+            Some(path) => given_path("geckodriver", path)?,
+            None => find_on_path("geckodriver").ok_or_else(|| DriverError::NotFound {
+                name: "geckodriver",
+                hint: format!(
+                    "pass --geckodriver <path>, set $GECKODRIVER or add it to the PATH \
+                     (downloads: {GECKODRIVER_RELEASES})"
+                ),
+            })?,
+        };
+        let firefox = match &options.firefox {
+            Some(path) => given_path("firefox", path)?,
+            None => find_on_path("firefox")
+                .or_else(|| {
+                    default_firefox_paths()
+                        .iter()
+                        .map(PathBuf::from)
+                        .find(|p| p.is_file())
+                })
+                .ok_or_else(|| DriverError::NotFound {
+                    name: "firefox",
+                    hint:
+                        "install it, pass --firefox <path>, set $FIREFOX_BIN or add it to the PATH"
+                            .to_string(),
+                })?,
+        };
+
+        // firefox doesn't print its version on every platform, so an unknown version is let through
+        if let (Some(gd), Some(ff)) = (version(&geckodriver), version(&firefox)) {
+            check_versions(&gd, &ff)?;
+        }
+
+        Ok(Driver {
+            geckodriver,
+            firefox,
+        })
+    }
+
+    /// starts geckodriver with the located firefox on a free port,
+    /// keeping firefox profiles in a temp directory of its own.
+    ///
+    /// geckodriver's own output is dropped with `--log quiet`.
+    pub fn spawn(&self, log: &LogLevel) -> Result<RunningDriver, DriverError> {
+        let quiet = log.is_quiet();
+        let port = free_port()?;
+        let temp_dir = env::temp_dir().join(format!(
+            "manga_dl-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos())
+        ));
+        fs::create_dir_all(&temp_dir)?;
+
+        let child = Command::new(&self.geckodriver)
+            .arg("--port")
+            .arg(port.to_string())
+            .arg("--binary")
+            .arg(&self.firefox)
+            .arg("--profile-root")
+            .arg(&temp_dir)
+            .stdout(if quiet {
+                Stdio::null()
+            } else {
+                Stdio::inherit()
+            })
+            .stderr(if quiet {
+                Stdio::null()
+            } else {
+                Stdio::inherit()
+            })
+            .spawn();
+        match child {
+            Ok(child) => Ok(RunningDriver {
+                child,
+                port,
+                temp_dir,
+                quiet,
+            }),
+            Err(source) => {
+                let _ = fs::remove_dir_all(&temp_dir);
+                Err(DriverError::Spawn {
+                    path: self.geckodriver.clone(),
+                    source,
+                })
+            }
+        }
+    }
+}
+
+/// A geckodriver process started by [`Driver::spawn`].
+#[derive(Debug)]
+pub struct RunningDriver {
+    child: Child,
+    port: u16,
+    temp_dir: PathBuf,
+    /// whether cleanup errors are kept quiet, see [`LogLevel::is_quiet`]
+    quiet: bool,
+}
+
+impl RunningDriver {
+    /// url fantoccini connects to
+    pub fn url(&self) -> String {
+        format!("http://localhost:{}", self.port)
+    }
+
+    /// waits until geckodriver accepts connections
+    pub async fn wait_ready(&self, timeout: Duration) -> Result<(), DriverError> {
+        let start = Instant::now();
+        while TcpStream::connect((Ipv4Addr::LOCALHOST, self.port))
+            .await
+            .is_err()
+        {
+            if start.elapsed() > timeout {
+                return Err(DriverError::NotReady { port: self.port });
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for RunningDriver {
+    /// kills geckodriver and removes its temp directory.
+    ///
+    /// never panics, whatever couldn't be cleaned up is only reported.
+    fn drop(&mut self) {
+        let killed = self.child.kill().and_then(|_| self.child.wait());
+        if let (Err(e), false) = (killed, self.quiet) {
+            eprintln!("failed to stop geckodriver: {e}");
+        }
+        if let Err(e) = fs::remove_dir_all(&self.temp_dir) {
+            if e.kind() != std::io::ErrorKind::NotFound && !self.quiet {
+                eprintln!("failed to remove temp dir {}: {e}", self.temp_dir.display());
+            }
+        }
+    }
+}
+
+/// Owns geckodriver and the browser session of a run.
+///
+/// both are closed when it's dropped, so a panic or an early return
+/// never leaves geckodriver, firefox or their temp directory behind.
+#[derive(Debug)]
+pub struct BrowserSession {
+    /// only taken by [`BrowserSession::close`] and on drop
+    client: Option<Client>,
+    /// only held to be dropped after the client, `None` for a `webdriver` that was already running
+    _driver: Option<RunningDriver>,
+    state: SessionState,
+    quiet: bool,
+}
+
+impl BrowserSession {
+    /// starts geckodriver and opens a browser session on it,
+    /// or only opens the session if `options.webdriver` points to one that's already running
+    pub async fn start(options: &DownloadOptions) -> Result<Self, DriverError> {
+        if let Some(webdriver) = &options.webdriver {
+            let client = start_client(&options.log, webdriver).await?;
+            return Ok(BrowserSession {
+                client: Some(client),
+                _driver: None,
+                state: SessionState::default(),
+                quiet: options.log.is_quiet(),
+            });
+        }
+
+        let driver = Driver::locate(options)?.spawn(&options.log)?;
+        driver.wait_ready(Duration::from_secs(10)).await?;
+        let client = start_client(&options.log, &driver.url()).await?;
+
+        Ok(BrowserSession {
+            client: Some(client),
+            _driver: Some(driver),
+            state: SessionState::default(),
+            quiet: options.log.is_quiet(),
+        })
+    }
+
+    pub fn client(&self) -> &Client {
+        self.client
+            .as_ref()
+            .expect("the client is only taken when the session closes")
+    }
+
+    /// a fresh reader for the chapter the client is about to open
+    pub fn reader(&self) -> Reader<'_> {
+        Reader::new(self.client(), &self.state)
+    }
+
+    /// ends the browser session, then stops geckodriver
+    pub async fn close(mut self) {
+        if let Some(client) = self.client.take() {
+            if let (Err(e), false) = (client.close().await, self.quiet) {
+                eprintln!("failed to close the browser: {e}");
+            }
+        }
+    }
+}
+
+impl Drop for BrowserSession {
+    fn drop(&mut self) {
+        // only left after a panic or an early return; the driver is killed right after,
+        // but closing the session first is what makes geckodriver quit firefox
+        let Some(client) = self.client.take() else {
+            return;
+        };
+        let Ok(handle) = Handle::try_current() else {
+            return;
+        };
+        if handle.runtime_flavor() == RuntimeFlavor::MultiThread {
+            task::block_in_place(|| {
+                handle.block_on(async {
+                    let _ = timeout(Duration::from_secs(5), client.close()).await;
+                })
+            });
+        }
+    }
+}
+
+async fn start_client(
+    log: &LogLevel,
+    webdriver: &str,
+) -> Result<Client, fantoccini::error::NewSessionError> {
+    let mut builder = fantoccini::ClientBuilder::native();
+
+    if log != &LogLevel::Full {
+        let caps: serde_json::Map<String, serde_json::Value> = json!({
+            "moz:firefoxOptions": {
+                "args": ["-headless"]
+            }
+        })
+        .as_object()
+        .expect("failed to serialize caps")
+        .clone();
+
+        builder.capabilities(caps);
+    }
+
+    builder.connect(webdriver).await
+}
+
+/// a port nothing is listening on right now, picked by the os
+fn free_port() -> Result<u16, DriverError> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    Ok(listener.local_addr()?.port())
+}
+
+fn given_path(name: &'static str, path: &Path) -> Result<PathBuf, DriverError> {
+    match path.is_file() {
+        true => Ok(path.to_path_buf()),
+        false => Err(DriverError::NotFound {
+            name,
+            hint: format!("{} does not exist", path.display()),
+        }),
+    }
+}
+
+/// looks for an executable in every directory of the PATH
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    let exe = match cfg!(target_os = "windows") {
+        true => format!("{name}.exe"),
+        false => name.to_string(),
+    };
+    let paths = env::var_os("PATH")?;
+
+    env::split_paths(&paths)
+        .map(|dir| dir.join(&exe))
+        .find(|path| path.is_file())
+}
+
+/// where the firefox installers put it when it isn't on the PATH
+fn default_firefox_paths() -> &'static [&'static str] {
+    if cfg!(target_os = "windows") {
+        &[
+            r"C:\Program Files\Mozilla Firefox\firefox.exe",
+            r"C:\Program Files (x86)\Mozilla Firefox\firefox.exe",
+        ]
+    } else if cfg!(target_os = "macos") {
+        &["/Applications/Firefox.app/Contents/MacOS/firefox"]
+    } else {
+        &[
+            "/usr/lib/firefox/firefox",
+            "/usr/lib64/firefox/firefox",
+            "/opt/firefox/firefox",
+            "/snap/bin/firefox",
+        ]
+    }
+}
+
+/// the version an executable prints with `--version`
+fn version(path: &Path) -> Option<String> {
+    let out = Command::new(path).arg("--version").output().ok()?;
+    parse_version(&String::from_utf8_lossy(&out.stdout))
+}
+
+/// the first dotted version number in `--version` output,
+/// ie. `geckodriver 0.35.0 (...)` or `Mozilla Firefox 128.0.3esr`
+fn parse_version(text: &str) -> Option<String> {
+    static VERSION_RE: OnceLock<Regex> = OnceLock::new();
+    let re = VERSION_RE.get_or_init(|| Regex::new(r"\d+(?:\.\d+)+").expect("valid regex"));
+
+    re.find(text.lines().next()?)
+        .map(|m| m.as_str().to_string())
+}
+
+/// oldest firefox release a geckodriver release supports,
+/// from https://firefox-source-docs.mozilla.org/testing/geckodriver/Support.html
+fn min_firefox(geckodriver: &str) -> u32 {
+    let mut parts = geckodriver
+        .split('.')
+        .map(|n| n.parse::<u32>().unwrap_or(0));
+    let (major, minor) = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+
+    match (major, minor) {
+        (0, 36..) | (1.., _) => 128,
+        (0, 34..=35) => 115,
+        (0, 32..=33) => 102,
+        (0, 31) => 91,
+        (0, 30) => 78,
+        _ => 60,
+    }
+}
+
+fn check_versions(geckodriver: &str, firefox: &str) -> Result<(), DriverError> {
+    let min = min_firefox(geckodriver);
+    let major: u32 = firefox
+        .split('.')
+        .next()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+
+    match major >= min {
+        true => Ok(()),
+        false => Err(DriverError::Incompatible {
+            geckodriver: geckodriver.to_string(),
+            firefox: firefox.to_string(),
+            min_firefox: min,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_ports_are_bindable() -> Result<(), DriverError> {
+        let port = free_port()?;
+        assert_ne!(port, 0);
+        TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        Ok(())
+    }
+
+    #[test]
+    fn version_compatibility() {
+        assert_eq!(
+            parse_version("geckodriver 0.35.0 (9f0a0036bea4 2024-08-03 07:11 +0000)\n").as_deref(),
+            Some("0.35.0")
+        );
+        assert_eq!(
+            parse_version("Mozilla Firefox 128.0.3esr").as_deref(),
+            Some("128.0.3")
+        );
+
+        assert!(check_versions("0.35.0", "128.0.3").is_ok());
+        assert!(check_versions("0.30.0", "91.0").is_ok());
+        assert!(matches!(
+            check_versions("0.36.0", "115.9.1"),
+            Err(DriverError::Incompatible {
+                min_firefox: 128,
+                ..
+            })
+        ));
+    }
+}
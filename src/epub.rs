@@ -0,0 +1,265 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use image::ImageReader;
+use zip::{write::SimpleFileOptions, CompressionMethod};
+
+use crate::{
+    manifest::sha256_hex,
+    output::{escape_xml, page_extension, utc_now, write_zip_atomic, ChapterPages},
+};
+
+/// a page image with everything the package document needs to know about it
+struct EpubPage {
+    /// manifest id shared by the image (`<id>-img`) and its xhtml page (`<id>`)
+    id: String,
+    image: String,
+    media_type: &'static str,
+    width: u32,
+    height: u32,
+    /// the page file on disk, only read while it's written to the archive
+    path: PathBuf,
+}
+
+/// packs one or more chapters into a fixed-layout EPUB 3 at `path`.
+///
+/// every page gets its own xhtml document sized to the image, the spine reads right to left
+/// and the first page doubles as the cover.
+pub fn write_epub(path: &Path, title: &str, chapters: &[ChapterPages]) -> io::Result<()> {
+    let mut pages: Vec<Vec<EpubPage>> = Vec::with_capacity(chapters.len());
+    for (c, chapter) in chapters.iter().enumerate() {
+        let mut chapter_pages = Vec::with_capacity(chapter.files.len());
+        for (p, page) in chapter.paths().enumerate() {
+            let (width, height) = ImageReader::open(&page)?
+                .with_guessed_format()?
+                .into_dimensions()
+                .map_err(io::Error::other)?;
+            let ext = page_extension(&page).to_string();
+            let id = format!("c{:03}-p{:04}", c + 1, p + 1);
+
+            chapter_pages.push(EpubPage {
+                image: format!("images/{id}.{ext}"),
+                id,
+                media_type: media_type(&ext),
+                width,
+                height,
+                path: page,
+            });
+        }
+        pages.push(chapter_pages);
+    }
+    if pages.iter().all(|p| p.is_empty()) {
+        return Err(io::Error::other("no pages to write to the epub"));
+    }
+
+    let identifier = {
+        let sources: Vec<&str> = chapters.iter().map(|c| c.source_url.as_str()).collect();
+        format!(
+            "urn:manga_dl:{}",
+            &sha256_hex(sources.join("\n").as_bytes())[..32]
+        )
+    };
+
+    // the mimetype entry has to come first and be stored uncompressed
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    write_zip_atomic(path, |zip| {
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+
+        zip.start_file("META-INF/container.xml", deflated)?;
+        zip.write_all(CONTAINER_XML.as_bytes())?;
+
+        zip.start_file("OEBPS/content.opf", deflated)?;
+        zip.write_all(package_document(title, &identifier, &pages).as_bytes())?;
+
+        zip.start_file("OEBPS/nav.xhtml", deflated)?;
+        zip.write_all(nav_document(title, chapters, &pages).as_bytes())?;
+
+        for page in pages.iter().flatten() {
+            zip.start_file(format!("OEBPS/{}.xhtml", page.id), deflated)?;
+            zip.write_all(page_document(title, page).as_bytes())?;
+
+            zip.start_file(format!("OEBPS/{}", page.image), stored)?;
+            zip.write_all(&fs::read(&page.path)?)?;
+        }
+
+        Ok(())
+    })
+}
+
+fn media_type(ext: &str) -> &'static str {
+    match ext.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn package_document(title: &str, identifier: &str, pages: &[Vec<EpubPage>]) -> String {
+    let (year, month, day, hour, minute, second) = utc_now();
+    let modified = format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z");
+
+    let mut items = String::new();
+    let mut itemrefs = String::new();
+    for (i, page) in pages.iter().flatten().enumerate() {
+        let cover = if i == 0 {
+            r#" properties="cover-image""#
+        } else {
+            ""
+        };
+        items.push_str(&format!(
+            "    <item id=\"{id}-img\" href=\"{image}\" media-type=\"{media}\"{cover}/>\n    \
+             <item id=\"{id}\" href=\"{id}.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+            id = page.id,
+            image = page.image,
+            media = page.media_type,
+        ));
+        itemrefs.push_str(&format!("    <itemref idref=\"{}\"/>\n", page.id));
+    }
+    let cover_id = pages
+        .iter()
+        .flatten()
+        .next()
+        .map(|page| format!("{}-img", page.id))
+        .unwrap_or_default();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="bookid" xml:lang="ja">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="bookid">{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>ja</dc:language>
+    <meta property="dcterms:modified">{modified}</meta>
+    <meta property="rendition:layout">pre-paginated</meta>
+    <meta property="rendition:orientation">auto</meta>
+    <meta property="rendition:spread">landscape</meta>
+    <meta name="cover" content="{cover_id}"/>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{items}  </manifest>
+  <spine page-progression-direction="rtl">
+{itemrefs}  </spine>
+</package>
+"#,
+        title = escape_xml(title),
+    )
+}
+
+fn nav_document(title: &str, chapters: &[ChapterPages], pages: &[Vec<EpubPage>]) -> String {
+    let mut toc = String::new();
+    for (chapter, chapter_pages) in chapters.iter().zip(pages) {
+        if let Some(first) = chapter_pages.first() {
+            toc.push_str(&format!(
+                "        <li><a href=\"{}.xhtml\">{}</a></li>\n",
+                first.id,
+                escape_xml(&chapter.title)
+            ));
+        }
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+  <head>
+    <title>{title}</title>
+  </head>
+  <body>
+    <nav epub:type="toc" id="toc">
+      <h1>{title}</h1>
+      <ol>
+{toc}      </ol>
+    </nav>
+  </body>
+</html>
+"#,
+        title = escape_xml(title),
+    )
+}
+
+fn page_document(title: &str, page: &EpubPage) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+  <head>
+    <title>{title}</title>
+    <meta name="viewport" content="width={width}, height={height}"/>
+    <style>html, body {{ margin: 0; padding: 0; }} img {{ display: block; }}</style>
+  </head>
+  <body>
+    <img src="{image}" width="{width}" height="{height}" alt=""/>
+  </body>
+</html>
+"#,
+        title = escape_xml(title),
+        width = page.width,
+        height = page.height,
+        image = page.image,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::{fs::File, io::Read};
+    use zip::ZipArchive;
+
+    #[test]
+    fn fixed_layout_rtl_epub() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new("epub");
+        image::RgbImage::new(20, 30).save(dir.join("1.png"))?;
+        image::RgbImage::new(40, 30).save(dir.join("2.png"))?;
+
+        let chapter = ChapterPages {
+            title: "chapter-1".to_string(),
+            dir: dir.0.clone(),
+            files: vec!["1.png".to_string(), "2.png".to_string()],
+            source_url: "https://rawmanga.net/manga/x/di-1hua".to_string(),
+        };
+        let path = dir.join("x.epub");
+        write_epub(&path, "x & y", &[chapter])?;
+
+        let mut archive = ZipArchive::new(File::open(&path)?)?;
+        assert_eq!(archive.by_index(0)?.name(), "mimetype");
+        assert_eq!(
+            archive.by_index(0)?.compression(),
+            CompressionMethod::Stored
+        );
+
+        let mut opf = String::new();
+        archive
+            .by_name("OEBPS/content.opf")?
+            .read_to_string(&mut opf)?;
+        assert!(opf.contains(r#"<spine page-progression-direction="rtl">"#));
+        assert!(opf.contains("pre-paginated"));
+        assert!(opf.contains("<dc:title>x &amp; y</dc:title>"));
+        assert!(opf.contains(
+            r#"href="images/c001-p0001.png" media-type="image/png" properties="cover-image""#
+        ));
+
+        let mut page = String::new();
+        archive
+            .by_name("OEBPS/c001-p0002.xhtml")?
+            .read_to_string(&mut page)?;
+        assert!(page.contains(r#"content="width=40, height=30""#));
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use base64::DecodeError;
 use thiserror::Error;
 
@@ -17,6 +19,8 @@ pub enum MainError {
     JoinError(#[from] tokio::task::JoinError),
     #[error("{0}")]
     DownloadImage(#[from] DownloadImageError),
+    #[error("{0}")]
+    Driver(#[from] DriverError),
 }
 
 #[warn(dead_code)]
@@ -37,11 +41,41 @@ pub enum DownloadImageError {
     #[error("{0}")]
     Reqwest(#[from] reqwest::Error),
     #[error("get request failed for src url: {0}; reason: {1}")]
-    GetReqwest(String, String),
+    GetReqwest(String, reqwest::Error),
+    #[error("get request for src url: {0} returned {1}")]
+    Status(String, reqwest::StatusCode),
     #[error("could not get canvas element from selector: {0}")]
     MissingCanvasElement(String),
     #[error("failed to find image: page number: {0};")]
     MissingImgElement(String),
+    #[error("could not convert page: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("not a valid page image: {0}")]
+    InvalidImage(String),
+    #[error("not tried, the reader was stuck after {0} pages in a row failed")]
+    ReaderStuck(usize),
+}
+
+impl DownloadImageError {
+    /// name of the variant, for the `--report` file
+    pub fn variant(&self) -> &'static str {
+        match self {
+            DownloadImageError::ColorEyre(_) => "ColorEyre",
+            DownloadImageError::Base64(_) => "Base64",
+            DownloadImageError::InvalidDataUrl(_) => "InvalidDataUrl",
+            DownloadImageError::CanvasScript(_) => "CanvasScript",
+            DownloadImageError::Fantoccini(_) => "Fantoccini",
+            DownloadImageError::Io(_) => "Io",
+            DownloadImageError::Reqwest(_) => "Reqwest",
+            DownloadImageError::GetReqwest(..) => "GetReqwest",
+            DownloadImageError::Status(..) => "Status",
+            DownloadImageError::MissingCanvasElement(_) => "MissingCanvasElement",
+            DownloadImageError::MissingImgElement(_) => "MissingImgElement",
+            DownloadImageError::Image(_) => "Image",
+            DownloadImageError::InvalidImage(_) => "InvalidImage",
+            DownloadImageError::ReaderStuck(_) => "ReaderStuck",
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -54,6 +88,49 @@ pub enum MangaReaderError {
     ColorEyre(#[from] color_eyre::Report),
 }
 
+#[derive(Error, Debug)]
+pub enum DriverError {
+    #[error("{name} was not found: {hint}")]
+    NotFound { name: &'static str, hint: String },
+    #[error(
+        "geckodriver {geckodriver} needs firefox {min_firefox} or newer, found firefox {firefox}"
+    )]
+    Incompatible {
+        geckodriver: String,
+        firefox: String,
+        min_firefox: u32,
+    },
+    #[error("failed to start {}: {source}", path.display())]
+    Spawn {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("geckodriver didn't start listening on port {port}")]
+    NotReady { port: u16 },
+    #[error("failed to open a browser session: {0}")]
+    Session(#[from] fantoccini::error::NewSessionError),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum SiteDefinitionError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse site definition {}: {source}", path.display())]
+    Toml {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("failed to parse site definition {}: {source}", path.display())]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("invalid site definition {}: {reason}", path.display())]
+    Invalid { path: PathBuf, reason: String },
+}
+
 #[derive(Error, Debug)]
 pub enum ArgError {
     #[error("{0}")]
@@ -70,6 +147,12 @@ pub enum ArgError {
         example: String,
     },
 
+    #[error("{0}")]
+    SiteDefinition(#[from] SiteDefinitionError),
+
     #[error("--url argument: {0} is not a supported site.\nrun with --help for a list of supported sites.")]
     WebsiteNotSupported(String),
+
+    #[error("retry: {} does not exist", .0.display())]
+    MissingFailureLog(PathBuf),
 }
@@ -0,0 +1,161 @@
+//! The failure log: every page that ran out of attempts, one json object per line,
+//! so `manga_dl retry <log>` can fetch exactly those pages again.
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::output::write_file_atomic;
+
+pub const FAILURE_LOG_FILE: &str = "manga_dl_errors.log";
+
+/// A line of the failure log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FailedPage {
+    /// the chapter url the page belongs to
+    pub url: String,
+    /// name of the [`crate::SiteAdapter`] the url was matched to
+    pub site: String,
+    /// the chapter folder the page is written to
+    pub dir: String,
+    /// 0-based page index, as in the chapter's manifest
+    pub index: usize,
+    /// the image url, or the site name for pages captured from the browser
+    pub source: String,
+    /// name of the [`crate::error::DownloadImageError`] variant
+    pub variant: String,
+    pub error: String,
+    pub attempts: u32,
+}
+
+/// adds a failed page to the end of the log, creating it if needed
+pub fn append(path: &Path, page: &FailedPage) -> io::Result<()> {
+    let mut f = OpenOptions::new().append(true).create(true).open(path)?;
+
+    let mut line = serde_json::to_string(page)?;
+    line.push('\n');
+    f.write_all(line.as_bytes())?;
+    f.flush()
+}
+
+/// reads every entry of the log, blank lines are skipped
+pub fn read(path: &Path) -> io::Result<Vec<FailedPage>> {
+    let text = fs::read_to_string(path)?;
+
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} line {}: {e}", path.display(), i + 1),
+                )
+            })
+        })
+        .collect()
+}
+
+/// replaces the log with `pages`, or removes it once nothing is left to retry
+pub fn rewrite(path: &Path, pages: &[FailedPage]) -> io::Result<()> {
+    if pages.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    write_file_atomic(path, |mut file| {
+        for page in pages {
+            serde_json::to_writer(&mut file, page)?;
+            file.write_all(b"\n")?;
+        }
+        Ok(file)
+    })
+}
+
+/// removes the entries of a chapter's pages that were written since they failed,
+/// so `retry` doesn't fetch them again
+pub fn remove(path: &Path, url: &str, dir: &str, indexes: &[usize]) -> io::Result<()> {
+    let pages = match read(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        pages => pages?,
+    };
+    let kept: Vec<FailedPage> = pages
+        .iter()
+        .filter(|page| !(page.url == url && page.dir == dir && indexes.contains(&page.index)))
+        .cloned()
+        .collect();
+
+    match kept.len() == pages.len() {
+        true => Ok(()),
+        false => rewrite(path, &kept),
+    }
+}
+
+/// the entries of each chapter, keyed by (chapter url, folder) in log order
+pub fn by_chapter(pages: Vec<FailedPage>) -> BTreeMap<(String, String), Vec<FailedPage>> {
+    let mut chapters: BTreeMap<(String, String), Vec<FailedPage>> = BTreeMap::new();
+    for page in pages {
+        let entries = chapters
+            .entry((page.url.clone(), page.dir.clone()))
+            .or_default();
+        // a page that failed in several runs is only retried once
+        match entries.iter_mut().find(|e| e.index == page.index) {
+            Some(entry) => *entry = page,
+            None => entries.push(page),
+        }
+    }
+
+    chapters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn failed(url: &str, index: usize, error: &str) -> FailedPage {
+        FailedPage {
+            url: url.to_string(),
+            site: "RawManga".to_string(),
+            dir: "./download/x".to_string(),
+            index,
+            source: format!("https://cdn.example.com/{index}.jpg"),
+            variant: "Status".to_string(),
+            error: error.to_string(),
+            attempts: 3,
+        }
+    }
+
+    #[test]
+    fn round_trip() -> io::Result<()> {
+        let dir = TempDir::new("log");
+        let path = dir.join(FAILURE_LOG_FILE);
+
+        let a = failed("https://rawmanga.net/manga/x/di-1hua", 2, "404");
+        let b = failed("https://rawmanga.net/manga/x/di-1hua", 2, "timeout");
+        let c = failed("https://rawmanga.net/manga/x/di-2hua", 0, "404");
+        for page in [&a, &b, &c] {
+            append(&path, page)?;
+        }
+        assert_eq!(read(&path)?, [a, b.clone(), c.clone()]);
+
+        let chapters = by_chapter(read(&path)?);
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters.values().next().unwrap(), &[b]);
+
+        rewrite(&path, std::slice::from_ref(&c))?;
+        assert_eq!(read(&path)?, [c]);
+        rewrite(&path, &[])?;
+        assert!(!path.exists());
+
+        fs::write(&path, "{\"url\": 1}\n")?;
+        assert_eq!(read(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+}
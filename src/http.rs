@@ -0,0 +1,85 @@
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use reqwest::{Client as ReqClient, Url as ReqUrl};
+use scraper::{Html, Selector};
+
+use crate::site::{parse_chapter_number, sort_chapters, Chapter};
+
+/// fetches the html of a page, for sites that are scraped without a browser
+pub async fn fetch_html(req_client: &ReqClient, url: &str) -> Result<String> {
+    let res = req_client
+        .get(url)
+        .send()
+        .await
+        .wrap_err_with(|| format!("failed to get {url}"))?;
+    if !res.status().is_success() {
+        return Err(eyre!("get request for {url} returned {}", res.status()));
+    }
+
+    Ok(res.text().await?)
+}
+
+/// the `attr` of every element matched by `selector`, resolved against the page's `url`.
+///
+/// elements without the attribute are skipped.
+pub fn select_urls(html: &Html, selector: &str, attr: &str, url: &str) -> Result<Vec<String>> {
+    let selector =
+        Selector::parse(selector).map_err(|e| eyre!("invalid selector `{selector}`: {e}"))?;
+    let base = ReqUrl::parse(url)?;
+
+    let urls = html
+        .select(&selector)
+        .filter_map(|elm| elm.value().attr(attr))
+        .filter_map(|href| base.join(href.trim()).ok())
+        .map(String::from)
+        .collect();
+
+    Ok(urls)
+}
+
+/// the chapter links matched by `selector`, sorted by chapter number.
+///
+/// the html counterpart of [`crate::site::list_chapter_links`].
+pub fn select_chapter_links(html: &Html, selector: &str, url: &str) -> Result<Vec<Chapter>> {
+    let mut chapters: Vec<Chapter> = select_urls(html, selector, "href", url)?
+        .into_iter()
+        .filter_map(|url| {
+            let number = parse_chapter_number(&url)?;
+            Some(Chapter { number, url })
+        })
+        .collect();
+    sort_chapters(&mut chapters);
+
+    Ok(chapters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_links_are_resolved() -> Result<()> {
+        let html = Html::parse_document(
+            r#"<ul class="list-chapters">
+                <li><a href="/manga/x/di-2hua">2</a></li>
+                <li><a href="https://rawmanga.net/manga/x/di-1hua">1</a></li>
+                <li><a>no link</a></li>
+            </ul>"#,
+        );
+        let chapters =
+            select_chapter_links(&html, "ul.list-chapters a", "https://rawmanga.net/manga/x")?;
+        let urls: Vec<&str> = chapters.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://rawmanga.net/manga/x/di-1hua",
+                "https://rawmanga.net/manga/x/di-2hua"
+            ]
+        );
+        assert!(select_urls(&html, "a[", "href", "https://rawmanga.net").is_err());
+
+        Ok(())
+    }
+}
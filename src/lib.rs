@@ -0,0 +1,284 @@
+//! Downloads manga chapters and series from the sites in [`site::adapters`].
+//!
+//! [`download`] takes a list of [`Url`]s and [`DownloadOptions`], and reports what became of
+//! each url in a [`RunReport`]. Progress is passed to [`DownloadOptions::on_progress`].
+//! Nothing is printed when [`DownloadOptions::log`] is [`options::LogLevel::Quiet`].
+mod comicinfo;
+pub mod convert;
+pub mod definition;
+mod download;
+mod driver;
+mod epub;
+pub mod error;
+pub mod failure_log;
+mod http;
+mod loading;
+mod macros;
+mod mangagun;
+mod mangareader;
+mod manifest;
+pub mod naming;
+pub mod options;
+pub mod output;
+mod pdf;
+mod rawmanga;
+pub mod report;
+pub mod retry;
+pub mod shutdown;
+pub mod site;
+#[cfg(test)]
+mod test_util;
+pub mod trim;
+pub mod url;
+
+use std::{path::Path, time};
+
+#[allow(unused_imports)]
+use color_eyre::{
+    eyre::{eyre, Result},
+    owo_colors::OwoColorize,
+    Report,
+};
+use download::{dl_chapter, dl_series, retry_chapter};
+use driver::BrowserSession;
+use error::{MainError, MangaReaderError};
+use fantoccini::{Client, Locator};
+use loading::{print_pages_arg, print_reqerr_count};
+use report::UrlReport;
+use reqwest::{Client as ReqClient, ClientBuilder as ReqClientBuilder};
+use spinners::Spinner;
+
+pub use options::{DownloadOptions, ProgressCallback, ProgressEvent};
+pub use report::{RunReport, RunStatus};
+pub use site::SiteAdapter;
+pub use url::Url;
+
+/// downloads every url into [`DownloadOptions::root`].
+///
+/// a url that fails doesn't stop the others, it's reported in the [`RunReport`];
+/// errors are the ones that stop the whole run, like a browser that can't be started,
+/// or a second signal asking to quit, see [`shutdown`].
+pub async fn download(urls: &[Url], options: &DownloadOptions) -> Result<RunReport> {
+    options
+        .shutdown
+        .cancellable(download_urls(urls, options))
+        .await
+}
+
+async fn download_urls(urls: &[Url], options: &DownloadOptions) -> Result<RunReport> {
+    let instant = time::Instant::now();
+    // sites scraped over plain http don't need geckodriver or firefox
+    let browser = match urls.iter().any(|url| url.site.needs_browser()) {
+        true => Some(BrowserSession::start(options).await?),
+        false => None,
+    };
+    let c = browser.as_ref();
+    let mut errors: Vec<Report> = Vec::new();
+    let mut url_reports = Vec::with_capacity(urls.len());
+    let quiet = options.log.is_quiet();
+
+    if let (false, Some(pages)) = (quiet, &options.pages) {
+        print_pages_arg(pages);
+    }
+
+    let req_client = build_req_client(options)?;
+    let root = &options.root;
+    for url in urls.iter() {
+        let mut chapters = Vec::new();
+        let res = if options.shutdown.requested() {
+            Err(eyre!("interrupted before it was started"))
+        } else if url.series {
+            dl_series(c, &req_client, url, options, root, &mut chapters).await
+        } else {
+            let url = match &options.name {
+                Some(template) => template.apply(url, None, None),
+                None => url.clone(),
+            };
+            dl_chapter(c, &req_client, &url, options, root, &mut chapters).await
+        };
+
+        url_reports.push(UrlReport {
+            url: url.url.clone(),
+            title: url.title.clone(),
+            chapters,
+            error: res.as_ref().err().map(|e| format!("{e:#}")),
+        });
+        if let Err(e) = res {
+            errors.push(e);
+        };
+    }
+
+    if let Some(browser) = browser {
+        browser.close().await;
+    }
+
+    if !quiet {
+        if !errors.is_empty() {
+            let titles: Vec<String> = urls.iter().flat_map(|url| url.title.clone()).collect();
+            print_reqerr_count(errors.len(), &titles);
+            eprintln!("{}", style_text!("STDERR:", error));
+            for e in errors {
+                eprintln!("{:?}", e);
+            }
+        }
+        println!("\nelapsed: {}s", instant.elapsed().as_secs());
+    }
+
+    Ok(RunReport::new(
+        url_reports,
+        instant.elapsed(),
+        options.shutdown.requested(),
+    ))
+}
+
+/// downloads the pages of a failure log again and keeps only the ones that still fail in it,
+/// see [`failure_log`]
+pub async fn retry(log: &Path, options: &DownloadOptions) -> Result<RunReport> {
+    options.shutdown.cancellable(retry_log(log, options)).await
+}
+
+async fn retry_log(log: &Path, options: &DownloadOptions) -> Result<RunReport> {
+    let instant = time::Instant::now();
+    let mut chapters = Vec::new();
+    for ((url, _), pages) in failure_log::by_chapter(failure_log::read(log)?) {
+        // the site the url was matched to when it failed, even if it wouldn't be matched now
+        let name = &pages[0].site;
+        let site = site::adapter_named(name)
+            .ok_or_else(|| eyre!("{url}: there is no site named {name} anymore"))?;
+        chapters.push((Url::with_site(url, site), pages));
+    }
+    let total: usize = chapters.iter().map(|(_, pages)| pages.len()).sum();
+    let quiet = options.log.is_quiet();
+    if !quiet {
+        println!(
+            "retrying {} page(s) of {} chapter(s)",
+            style_text!(total, url),
+            style_text!(chapters.len(), url)
+        );
+    }
+
+    let browser = match chapters.iter().any(|(url, _)| url.site.needs_browser()) {
+        true => Some(BrowserSession::start(options).await?),
+        false => None,
+    };
+    let c = browser.as_ref();
+    let req_client = build_req_client(options)?;
+
+    let mut remaining = Vec::new();
+    let mut url_reports = Vec::with_capacity(chapters.len());
+    for (url, pages) in chapters {
+        // pages that weren't tried stay in the log as they were
+        if options.shutdown.requested() {
+            remaining.extend(pages);
+            continue;
+        }
+        let mut reports = Vec::new();
+        let error =
+            match retry_chapter(c, &req_client, &url, options, pages.clone(), &mut reports).await {
+                Ok(left) => {
+                    remaining.extend(left);
+                    None
+                }
+                Err(e) => {
+                    if !quiet {
+                        eprintln!("{:?}", e);
+                    }
+                    remaining.extend(pages);
+                    Some(format!("{e:#}"))
+                }
+            };
+        url_reports.push(UrlReport {
+            url: url.url.clone(),
+            title: url.title.clone(),
+            chapters: reports,
+            error,
+        });
+    }
+
+    if let Some(browser) = browser {
+        browser.close().await;
+    }
+    failure_log::rewrite(log, &remaining)?;
+    if !quiet {
+        println!(
+            "\n{} / {} page(s) recovered, elapsed: {}s",
+            style_text!(total - remaining.len(), url),
+            style_text!(total, url),
+            instant.elapsed().as_secs()
+        );
+    }
+
+    Ok(RunReport::new(
+        url_reports,
+        instant.elapsed(),
+        options.shutdown.requested(),
+    ))
+}
+
+/// one reqwest client for the whole run so connections are reused
+fn build_req_client(options: &DownloadOptions) -> Result<ReqClient> {
+    Ok(ReqClientBuilder::new().timeout(options.timeout).build()?)
+}
+
+/// handle any redirect ads by closing the newly opened tab
+pub async fn g_close_open_window(c: &Client) -> Result<(), MangaReaderError> {
+    let handles = c.windows().await?;
+    if handles.len() > 1 {
+        for handle in handles.iter().skip(1) {
+            c.switch_to_window(handle.clone()).await?;
+            c.close_window().await?;
+        }
+        c.switch_to_window(handles[0].clone()).await?;
+    }
+
+    Ok(())
+}
+
+/// hides anything with a z-index of `2147483647`.
+pub async fn g_handle_popup(c: &Client) -> Result<(), MainError> {
+    if let Ok(e) = c
+        .find(Locator::Css("*[style*='z-index: 2147483647']"))
+        .await
+    {
+        e.click().await?;
+
+        c.execute(
+            r#"
+            var ad = document.querySelector("*[style*='z-index: 2147483647']");
+            ad.style.display = 'none';  
+            "#,
+            vec![],
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// the title and folder of a url
+pub type NavigateGroup = (String, String);
+
+/// names the url's folder `<parent>/<title>` and navigates to the url if its site needs the browser
+pub async fn setup_nav(
+    client: Option<&Client>,
+    url: &Url,
+    parent: &str,
+    options: &DownloadOptions,
+) -> Result<NavigateGroup> {
+    let title = naming::folder_name(url);
+    let dl_path = format!("{parent}/{title}");
+
+    let mut sp = (!options.log.is_quiet()).then(|| {
+        println!("\n{}", url.site.name());
+        let message = format!("{}: {}", "", style_text!(&title, url));
+        Spinner::new(spinners::Spinners::Arc, message)
+    });
+    if let (true, Some(client)) = (url.site.needs_browser(), client) {
+        client.goto(&url.url).await?;
+    }
+    if let Some(sp) = &mut sp {
+        sp.stop_with_newline();
+    }
+
+    Ok((title, dl_path))
+}
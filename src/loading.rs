@@ -1,11 +1,44 @@
 #![allow(dead_code)]
 
-use crate::style_text;
+use crate::{
+    options::{LogLevel, PageRanges},
+    style_text,
+};
 use color_eyre::owo_colors::OwoColorize;
-use std::{fmt::Debug, time::Duration};
+use spinners::{Spinner, Spinners};
+use std::{fmt::Debug, path::Path, time::Duration};
 
-pub fn print_indexes_arg(indexes: &Vec<usize>) {
-    println!("only downloading indexes: {:?}.", indexes);
+/// a progress spinner, or none with `--log quiet`
+pub fn spinner(log: &LogLevel, message: String) -> Option<Spinner> {
+    (!log.is_quiet()).then(|| Spinner::new(Spinners::Dots3, message))
+}
+
+pub fn print_pages_arg(pages: &PageRanges) {
+    println!("only downloading pages: {}.", pages);
+}
+
+pub fn print_resume_msg(done: usize, total: usize) {
+    println!(
+        "resuming: {} / {} pages already downloaded.",
+        style_text!(done, url),
+        style_text!(total, url)
+    );
+}
+
+pub fn print_packed_msg(path: &Path) {
+    println!("{}", style_text!(path.display().to_string(), path));
+}
+
+pub fn print_not_packed_msg(dl_path: &str) {
+    println!(
+        "{}: {} {}",
+        style_text!("WARNING", severe),
+        style_text!(dl_path, path),
+        style_text!(
+            "is incomplete; kept as a folder, finish it with --resume",
+            error
+        ),
+    );
 }
 
 pub fn downloading_panel_data_msg(i: u16, max: u16) -> String {
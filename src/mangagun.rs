@@ -1,97 +1,148 @@
-use std::{collections::HashSet, io::Write, time::Instant};
-
+use async_trait::async_trait;
+use color_eyre::owo_colors::OwoColorize;
 use color_eyre::{eyre::Context, Result};
 use fantoccini::{Client, Locator};
-use spinners::{Spinner, Spinners};
+use reqwest::{Client as ReqClient, Url as ReqUrl};
 use tokio::time::sleep;
 
 use crate::{
-    cli::{Cli, LogLevel, Url},
+    error::{ArgError, DownloadImageError},
     g_handle_popup,
-    loading::{downloading_panel_data_msg, print_download_complete_msg},
-    mangareader::{write_img, ImageData},
-    setup_nav,
+    options::DownloadOptions,
+    site::{
+        host_is, list_chapter_links, path_segments, Chapter, Page, PageSource, Reader, SiteAdapter,
+    },
+    style_text,
 };
 
-pub type NavigateGroup = (String, String, Spinner);
+/// how many times fetching a page waits 300ms for its loading gif to be replaced
+const LOADING_WAITS: u32 = 30;
 
-pub async fn dl_mangagun(client: &Client, url: &Url, args: &Cli) -> Result<()> {
-    let (_, dl_path, mut sp) = setup_nav(client, url, args).await?;
-    let start = Instant::now();
+#[derive(Debug)]
+pub struct MangaGun;
 
-    // hide the top-navbar
-    execute_set_element_hidden_inline(client, ".navbar").await?;
-    // hide the bottom-navbar
-    execute_set_element_hidden_inline(client, "#rd-side_icon").await?;
+pub static MANGAGUN: MangaGun = MangaGun;
 
-    let index_map: Option<HashSet<&usize>> =
-        args.indexes.as_ref().map(|slice| slice.iter().collect());
-    let img_data = get_all_images(&dl_path, client, &mut sp, index_map, &args.log).await?;
+#[async_trait]
+impl SiteAdapter for MangaGun {
+    fn name(&self) -> &'static str {
+        "MangaGun"
+    }
 
-    let elapsed = start.elapsed();
-    print_download_complete_msg(elapsed);
+    fn detect(&self, url: &ReqUrl) -> bool {
+        host_is(url, "mangagun.net")
+    }
 
-    //sp = Spinner::new(Spinners::Triangle, "".into());
+    /// every series and chapter is a single `.html` page at the root
+    fn validate(&self, url: &ReqUrl) -> Result<(), ArgError> {
+        let reason = match path_segments(url).as_slice() {
+            [page] if page.ends_with(".html") => return Ok(()),
+            [] => "mangagun URL is missing a series or chapter page",
+            _ => "mangagun URL must be a single .html page",
+        };
+
+        Err(ArgError::InvalidUrl {
+            url: url.to_string(),
+            reason: style_text!(reason),
+            example: style_text!("mangagun.net/gunchap-999-shmg-one-piece-raw.html"),
+        })
+    }
 
-    img_data
-        .into_iter()
-        .for_each(|data| write_img(&data).unwrap());
-    //sp.stop_with_newline();
+    fn title(&self, url: &str) -> Option<String> {
+        let url = ReqUrl::parse(url).ok()?;
+        path_segments(&url).last().map(|page| page.to_string())
+    }
 
-    Ok(())
-}
+    /// chapters are `gunchap-999-*.html`, anything else is a series page
+    fn is_series(&self, url: &str) -> bool {
+        ReqUrl::parse(url).is_ok_and(|url| {
+            !path_segments(&url)
+                .last()
+                .is_some_and(|page| page.starts_with("gunchap-"))
+        })
+    }
 
-async fn get_all_images(
-    dl_path: &str,
-    c: &Client,
-    sp: &mut Spinner,
-    index_map: Option<HashSet<&usize>>,
-    log: &LogLevel,
-) -> Result<HashSet<ImageData>> {
-    g_handle_popup(c).await.wrap_err(line!())?;
-    let imgs = c.find_all(Locator::Css("img.chapter-img")).await?;
-    let mut new_imgs: HashSet<ImageData> = HashSet::new();
-
-    let max = imgs.len();
-    // if the index map is Some, skip indexes that aren't specified
-    for (i, img) in imgs.into_iter().enumerate() {
-        if let Some(i_map) = &index_map {
-            if !i_map.contains(&i) {
-                continue;
-            }
-        }
-        execute_set_element_hidden_inline(c, "#adModal").await?;
-        execute_set_element_hidden_computed(c).await?;
+    /// gunchap-999-shmg-one-piece-raw.html
+    fn series(&self, url: &str) -> Option<String> {
+        let url = ReqUrl::parse(url).ok()?;
+        let page = *path_segments(&url).last()?;
+        let file = page.strip_prefix("gunchap-")?;
+        let (_, slug) = file.split_once("-shmg-")?;
+        let slug = slug.trim_end_matches(".html").trim_end_matches("-raw");
+        (!slug.is_empty()).then(|| slug.to_string())
+    }
+
+    /// raws are always japanese
+    fn language(&self, _url: &str) -> Option<String> {
+        Some("ja".to_string())
+    }
+
+    async fn list_chapters(&self, client: &Client) -> Result<Vec<Chapter>> {
+        g_handle_popup(client).await.wrap_err(line!())?;
+        list_chapter_links(client, "ul.list-chapters a").await
+    }
+
+    async fn list_pages(
+        &self,
+        reader: &Reader<'_>,
+        _options: &DownloadOptions,
+    ) -> Result<Vec<Page>> {
+        let client = reader.client;
+        // hide the top-navbar
+        execute_set_element_hidden_inline(client, ".navbar").await?;
+        // hide the bottom-navbar
+        execute_set_element_hidden_inline(client, "#rd-side_icon").await?;
+
+        g_handle_popup(client).await.wrap_err(line!())?;
+        let imgs = client.find_all(Locator::Css("img.chapter-img")).await?;
+
+        let pages = (0..imgs.len())
+            .map(|i| Page {
+                index: i,
+                source: PageSource::Browser,
+            })
+            .collect();
+
+        Ok(pages)
+    }
+
+    async fn fetch_page(
+        &self,
+        reader: &Reader<'_>,
+        _req_client: &ReqClient,
+        page: &Page,
+    ) -> Result<Vec<u8>, DownloadImageError> {
+        let client = reader.client;
+        let img = client
+            .find_all(Locator::Css("img.chapter-img"))
+            .await?
+            .into_iter()
+            .nth(page.index)
+            .ok_or_else(|| DownloadImageError::MissingImgElement(page.number().to_string()))?;
+
+        execute_set_element_hidden_inline(client, "#adModal").await?;
+        execute_set_element_hidden_computed(client).await?;
 
         // wait until the image src is not the loading GIF
+        let mut waits = 0;
         while let Some(src) = img.attr("src").await? {
             if !src.contains("gif") {
-                let rect = img.rectangle().await?;
-                // the spinner gif is 100 x 99.9...
-                if rect.2 > 300.0 {
-                    match log {
-                        LogLevel::Full => {
-                            print!(":\n  {src}");
-                            std::io::stdout().flush().expect("failed to flush output");
-                        }
-                        _ => { /* skip */ }
-                    }
-                    let msg = downloading_panel_data_msg(i as u16, max as u16);
-                    *sp = Spinner::new(Spinners::Arc, msg);
-                }
                 break;
             }
+            if waits == LOADING_WAITS {
+                return Err(DownloadImageError::InvalidImage(format!(
+                    "page {} is still the loading gif",
+                    page.number()
+                )));
+            }
+            waits += 1;
             sleep(std::time::Duration::from_millis(300)).await;
         }
 
         let bytes = img.screenshot().await?;
-        let path = format!("{dl_path}/{i}.jpg");
-        let img = ImageData { bytes, path };
 
-        new_imgs.insert(img);
+        Ok(bytes)
     }
-
-    Ok(new_imgs)
 }
 
 pub async fn execute_set_element_hidden_computed(c: &Client) -> Result<()> {
@@ -116,7 +167,7 @@ pub async fn execute_set_element_hidden_inline(c: &Client, selector: &str) -> Re
             var element = document.querySelector('{}');
             if (element) {{
                 element.style.display = 'none';
-            }} 
+            }}
             "#,
         selector
     );
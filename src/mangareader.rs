@@ -1,140 +1,178 @@
+use async_trait::async_trait;
 use base64::prelude::*;
-use std::{collections::HashSet, fs::read_dir, time::Duration};
+use std::{sync::atomic::Ordering, time::Duration};
 
 use crate::{
-    cli::{Cli, Url},
-    error::{DownloadImageError, MangaReaderError},
+    error::{ArgError, DownloadImageError, MainError, MangaReaderError},
     g_close_open_window,
-    loading::print_reqerr_count,
-    setup_nav,
+    options::DownloadOptions,
+    retry::RetryPolicy,
+    site::{
+        host_is, parse_chapter_number, path_segments, sort_chapters, Chapter, Page, PageSource,
+        Reader, SiteAdapter,
+    },
+    style_text,
 };
-use crate::{error::MainError, loading::downloading_panel_data_msg};
 use color_eyre::{
     eyre::{eyre, Context, Result},
     owo_colors::OwoColorize,
     Section,
 };
 use fantoccini::{Client, Locator};
-use reqwest::{Client as ReqClient, ClientBuilder as ReqClientBuilder};
-use spinners::{Spinner, Spinners};
-use std::process::Command;
-
-#[derive(Eq, Hash, PartialEq, Debug)]
-pub struct ImageData {
-    pub bytes: Vec<u8>,
-    pub path: String,
-}
+use reqwest::{Client as ReqClient, Url as ReqUrl};
 
-#[derive(Eq, Hash, PartialEq, Debug)]
-pub struct ReqImageData {
-    pub url: String,
-    pub path: String,
-}
+#[derive(Debug)]
+pub struct MangaReader;
 
-/// Arguments
-///
-/// * `index` - needed because mangareader has a popup on render,
-pub async fn dl_mangareader(client: &Client, url: &Url, args: &Cli, index: usize) -> Result<()> {
-    let (title, dl_path, mut _sp) = setup_nav(client, url, args).await?;
-    //let start = Instant::now();
+pub static MANGAREADER: MangaReader = MangaReader;
 
-    if index == 0 {
-        select_reading_mode(client).await?;
+#[async_trait]
+impl SiteAdapter for MangaReader {
+    fn name(&self) -> &'static str {
+        "MangaReader"
     }
-    let max = count_pages(client).await? - 1;
 
-    // hold all the bytes and formatted paths of the imgs
-    // to write all at once at the very end
-    let mut img_data_vec: Vec<ImageData> = Vec::new();
+    fn detect(&self, url: &ReqUrl) -> bool {
+        host_is(url, "mangareader.to")
+    }
 
-    let req_client = ReqClientBuilder::new().timeout(Duration::from_millis(2500));
-    let req_client = req_client.build()?;
+    /// series are `/<series>`, chapters `/read/<series>/<language>/chapter-<n>`
+    fn validate(&self, url: &ReqUrl) -> Result<(), ArgError> {
+        let reason = match path_segments(url).as_slice() {
+            [] => "mangareader URL is missing a series or chapter path",
+            ["read", _, _, chapter] if chapter.starts_with("chapter-") => return Ok(()),
+            ["read", ..] => "mangareader chapter URL must be /read/<series>/<language>/chapter-<n>",
+            [series] if !series.contains('.') => return Ok(()),
+            _ => "mangareader URL is neither a series (/<series>) nor a chapter (/read/...)",
+        };
 
-    // if the panel_canvas is an <img> elemement get req is required
-    // hold src urls till the end via this vector to download concurrently
-    // (url, dl_path)
-    let mut src_urls: HashSet<ReqImageData> = HashSet::new();
-    let is_imgs = _find_images(client).await;
-    let mut errors = Vec::with_capacity(max.into());
+        Err(ArgError::InvalidUrl {
+            url: url.to_string(),
+            reason: style_text!(reason),
+            example: style_text!("mangareader.to/read/vagabond-4/ja/chapter-6"),
+        })
+    }
+
+    fn title(&self, url: &str) -> Option<String> {
+        let url = ReqUrl::parse(url).ok()?;
+        match path_segments(&url).as_slice() {
+            // mangareader.to/read/vagabond-4/ja/chapter-6
+            ["read", path @ ..] if !path.is_empty() => Some(path.join("-")),
+            // mangareader.to/vagabond-4
+            [.., slug] if !slug.contains('.') => Some(slug.to_string()),
+            _ => None,
+        }
+    }
+
+    fn is_series(&self, url: &str) -> bool {
+        ReqUrl::parse(url).is_ok_and(|url| !matches!(path_segments(&url).as_slice(), ["read", ..]))
+    }
+
+    /// mangareader.to/read/vagabond-4/ja/chapter-6
+    fn series(&self, url: &str) -> Option<String> {
+        let url = ReqUrl::parse(url).ok()?;
+        match path_segments(&url).as_slice() {
+            ["read", series, ..] => Some(series.to_string()),
+            _ => None,
+        }
+    }
 
-    for i in 0..max {
-        if errors.len() > 3 {
-            break;
+    fn language(&self, url: &str) -> Option<String> {
+        let url = ReqUrl::parse(url).ok()?;
+        match path_segments(&url).as_slice() {
+            ["read", _, language, ..] => Some(language.to_string()),
+            _ => None,
         }
-        if !is_imgs {
-            match download_panel_canvas(
-                i,
-                client,
-                &dl_path,
-                &mut img_data_vec,
-                Duration::from_secs(5),
-            )
+    }
+
+    async fn list_chapters(&self, client: &Client) -> Result<Vec<Chapter>> {
+        let items = client
+            .wait()
+            .at_most(Duration::from_millis(2000))
+            .for_element(Locator::Css("#ja-chapters"))
             .await
-            {
-                Ok(_) => (),
-                Err(DownloadImageError::MissingCanvasElement(_)) => {
-                    if let Err(e) = download_panel_img(i, client, &dl_path, &mut src_urls).await {
-                        errors.push(e);
-                    }
-                }
-                Err(e) => return Err(e.into()),
+            .wrap_err("chapter list not found")?
+            .find_all(Locator::Css("li.chapter-item"))
+            .await?;
+        let mut chapters = Vec::with_capacity(items.len());
+
+        for item in items {
+            let link = item.find(Locator::Css("a")).await?;
+            let Some(url) = link.prop("href").await? else {
+                continue;
             };
-        } else if let Err(img_err) = download_panel_img(i, client, &dl_path, &mut src_urls).await {
-            if let Err(canvas_err) = download_panel_canvas(
-                i,
-                client,
-                &dl_path,
-                &mut img_data_vec,
-                Duration::from_secs(5),
-            )
-            .await
-            {
-                errors.push(canvas_err);
-                errors.push(img_err);
+            let number = match item.attr("data-number").await? {
+                Some(n) => n.parse().ok(),
+                None => parse_chapter_number(&url),
             };
-        }
-        _sp = Spinner::new(Spinners::Dots3, downloading_panel_data_msg(i, max));
-        client.execute("hozNextImage()", vec![]).await?;
-    }
-    _sp.stop_with_newline();
-    let mut error_reports: Vec<color_eyre::Report> = Vec::with_capacity(src_urls.len());
-
-    if !src_urls.is_empty() {
-        for (i, img) in src_urls.into_iter().enumerate() {
-            let res = download_img_src(&img.url, img.path, &req_client).await;
-
-            match res {
-                Ok(r) => img_data_vec.push(r),
-                Err(e) => {
-                    let report = eyre!("failed on `{}. {}`: \n{}", i, img.url, e.to_string());
-                    error_reports.push(report);
-                }
+            if let Some(number) = number {
+                chapters.push(Chapter { number, url });
             }
         }
-    }
+        sort_chapters(&mut chapters);
 
-    //_sp.stop_with_newline();
+        Ok(chapters)
+    }
 
-    if !error_reports.is_empty() {
-        print_reqerr_count(error_reports.len(), &title);
-    } else if !errors.is_empty() {
-        print_reqerr_count(errors.len(), &title);
-        println!("{}", "STDERROR:\n".bright_red());
-        for e in errors.into_iter() {
-            eprintln!("{}", e.red());
+    async fn list_pages(
+        &self,
+        reader: &Reader<'_>,
+        options: &DownloadOptions,
+    ) -> Result<Vec<Page>> {
+        let client = reader.client;
+        // mangareader asks new profiles to select the reading mode once per session
+        if reader.session.needs_setup(self.name()) {
+            select_reading_mode(client).await?;
         }
+        let count = count_pages(client, &options.retry).await?;
+        let max = count
+            .checked_sub(1)
+            .ok_or_else(|| MainError::ParseCounterElement(count.to_string()))?;
+        reader
+            .images
+            .store(_find_images(client).await, Ordering::Relaxed);
+
+        let pages = (0..max as usize)
+            .map(|i| Page {
+                index: i,
+                source: PageSource::Browser,
+            })
+            .collect();
+
+        Ok(pages)
     }
 
-    //let elapsed = start.elapsed();
-    //print_download_complete_msg(elapsed);
-
-    //_sp = Spinner::new(Spinners::Triangle, style_text!("writing data to file.."));
-
-    img_data_vec
-        .into_iter()
-        .for_each(|data| write_img(&data).unwrap());
+    async fn fetch_page(
+        &self,
+        reader: &Reader<'_>,
+        req_client: &ReqClient,
+        page: &Page,
+    ) -> Result<Vec<u8>, DownloadImageError> {
+        let client = reader.client;
+        // the reader only shows one panel at a time, so walk it forward to the page
+        while reader.cursor.load(Ordering::Relaxed) < page.index {
+            client.execute("hozNextImage()", vec![]).await?;
+            reader.cursor.fetch_add(1, Ordering::Relaxed);
+        }
 
-    Ok(())
+        if !reader.images.load(Ordering::Relaxed) {
+            match download_panel_canvas(client, Duration::from_secs(5)).await {
+                Ok(bytes) => Ok(bytes),
+                Err(DownloadImageError::MissingCanvasElement(_)) => {
+                    let src = download_panel_img(page.number(), client).await?;
+                    download_img_src(&src, req_client).await
+                }
+                Err(e) => Err(e),
+            }
+        } else {
+            match download_panel_img(page.number(), client).await {
+                Ok(src) => download_img_src(&src, req_client).await,
+                Err(img_err) => download_panel_canvas(client, Duration::from_secs(5))
+                    .await
+                    .map_err(|_| img_err),
+            }
+        }
+    }
 }
 
 async fn _find_images(c: &Client) -> bool {
@@ -148,13 +186,7 @@ async fn _find_images(c: &Client) -> bool {
     false
 }
 
-async fn download_panel_canvas(
-    index: u16,
-    c: &Client,
-    dl_path: &str,
-    img_data_vec: &mut Vec<ImageData>,
-    dur: Duration,
-) -> Result<(), DownloadImageError> {
+async fn download_panel_canvas(c: &Client, dur: Duration) -> Result<Vec<u8>, DownloadImageError> {
     // Construct the selector with the provided index
     let selector = "div.ds-item.active .image-horizontal";
 
@@ -174,10 +206,9 @@ async fn download_panel_canvas(
         // Execute the script with the selector as argument
         let data_url = c
             .execute(script, vec![selector.into()])
-            .await
-            .expect("failed to execute js toDataUrl() script")
+            .await?
             .as_str()
-            .unwrap()
+            .ok_or_else(|| DownloadImageError::MissingCanvasElement(selector.to_string()))?
             .trim()
             .to_string();
 
@@ -187,34 +218,18 @@ async fn download_panel_canvas(
             .ok_or_else(|| DownloadImageError::InvalidDataUrl(data_url.clone()))?;
 
         // Decode the Base64 string into binary data
-        let decoded_data = BASE64_STANDARD
-            .decode(base64_data)
-            .expect("failed to decode base64 string into binary.");
-
-        let index = index + 1;
-        let path = format!("{dl_path}/{index}.jpg");
+        let decoded_data = BASE64_STANDARD.decode(base64_data)?;
 
-        // Add the image data to the vector
-        let img = ImageData {
-            bytes: decoded_data,
-            path,
-        };
-        img_data_vec.push(img);
+        Ok(decoded_data)
     } else {
-        return Err(DownloadImageError::MissingCanvasElement(
-            dl_path.to_string(),
-        ));
+        Err(DownloadImageError::MissingCanvasElement(
+            selector.to_string(),
+        ))
     }
-
-    Ok(())
 }
 
-async fn download_panel_img(
-    index: u16,
-    c: &Client,
-    dl_path: &str,
-    src_urls: &mut HashSet<ReqImageData>,
-) -> Result<(), DownloadImageError> {
+/// returns the src url of the active panel
+async fn download_panel_img(number: usize, c: &Client) -> Result<String, DownloadImageError> {
     // Try to locate the image element using the CSS selector
     if let Ok(elm) = c
         .wait()
@@ -229,49 +244,40 @@ async fn download_panel_img(
             // Ensure the URL is valid before attempting to download
             let img_url = img_url.trim();
             if !img_url.is_empty() {
-                let index = index + 1;
-                let path = format!("{dl_path}/{index}.jpg");
-                let img = ReqImageData {
-                    url: img_url.to_string(),
-                    path,
-                };
-                src_urls.insert(img);
+                return Ok(img_url.to_string());
             }
         }
-    } else {
-        let path = format!("{index}.jpg");
-        return Err(DownloadImageError::MissingImgElement(path));
     }
 
-    Ok(())
+    Err(DownloadImageError::MissingImgElement(number.to_string()))
 }
 
-pub async fn download_img_src(
-    url: &str,
-    path: String,
-    c: &ReqClient,
-) -> Result<ImageData, DownloadImageError> {
+pub async fn download_img_src(url: &str, c: &ReqClient) -> Result<Vec<u8>, DownloadImageError> {
     let res = c
         .get(url)
         .send()
         .await
-        .map_err(|e| DownloadImageError::GetReqwest(url.to_string(), e.to_string()))?;
+        .map_err(|e| DownloadImageError::GetReqwest(url.to_string(), e))?;
+    if !res.status().is_success() {
+        return Err(DownloadImageError::Status(url.to_string(), res.status()));
+    }
     let bytes = res
         .bytes()
         .await
-        .wrap_err(format!("failed to decode src_url to bytes: {url}"))?
+        .map_err(|e| DownloadImageError::GetReqwest(url.to_string(), e))?
         .to_vec();
-    let img = ImageData { bytes, path };
 
-    Ok(img)
+    Ok(bytes)
 }
 
-async fn count_pages(c: &Client) -> Result<u16, MainError> {
+async fn count_pages(c: &Client, policy: &RetryPolicy) -> Result<u16, MainError> {
     let selector = Locator::Css("span.hoz-total-image");
-    let pgs_elm = c
-        .wait()
-        .at_most(Duration::from_millis(1000))
-        .for_element(selector)
+    let pgs_elm = policy
+        .run(|| {
+            c.wait()
+                .at_most(Duration::from_millis(1000))
+                .for_element(selector)
+        })
         .await
         .map_err(|e| {
             eyre!("total pages element not found:\n  {}{:?}", e, selector)
@@ -284,40 +290,6 @@ async fn count_pages(c: &Client) -> Result<u16, MainError> {
         .map_err(|_| MainError::ParseCounterElement(text))
 }
 
-pub fn write_img(data: &ImageData) -> Result<()> {
-    std::fs::write(&data.path, &data.bytes)?;
-    Ok(())
-}
-
-#[allow(dead_code)]
-pub fn crop_imgs(dl_path: &str) -> Result<(), MainError> {
-    for (i, entry) in read_dir(dl_path)?.enumerate() {
-        let entry = entry?;
-        let path = entry.path();
-
-        let path = path.to_string_lossy();
-
-        Command::new("magick")
-            .arg(path.as_ref()) // Path to the image
-            .arg("-fuzz")
-            .arg("10%")
-            .arg("-fill")
-            .arg("tran_sparent")
-            .arg("-opaque")
-            .arg("rgb(17,17,17)")
-            .arg("-trim")
-            .arg("+repage")
-            .arg(path.as_ref()) // Output file name
-            .spawn()
-            .expect("error launching magick")
-            .wait()?;
-
-        print!("\r{i}");
-    }
-
-    Ok(())
-}
-
 /// exists because mangareader asks new profiles to select the orientation of the reader
 async fn select_reading_mode(c: &Client) -> Result<(), MangaReaderError> {
     if let Ok(btn) = c
@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Progress of a chapter download, kept next to its pages so an interrupted
+/// run can be picked up again with --resume.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub source_url: String,
+    /// number of pages the site listed for the chapter
+    pub expected_pages: usize,
+    /// keyed by the 0-based page index
+    pub pages: BTreeMap<usize, PageEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PageEntry {
+    /// file name inside the chapter folder, empty for a failed page
+    pub file: String,
+    pub status: PageStatus,
+    pub size: Option<u64>,
+    /// hex encoded sha256 of the file
+    pub sha256: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PageStatus {
+    Done,
+    Failed,
+}
+
+impl Manifest {
+    pub fn new(source_url: &str, expected_pages: usize) -> Self {
+        Manifest {
+            source_url: source_url.to_string(),
+            expected_pages,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    pub fn path(dir: &str) -> PathBuf {
+        Path::new(dir).join(MANIFEST_FILE)
+    }
+
+    /// reads the manifest of a chapter folder, if it has one
+    pub fn load(dir: &str) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(Self::path(dir)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let manifest = serde_json::from_str(&text)?;
+
+        Ok(Some(manifest))
+    }
+
+    /// writes the manifest next to a temp file first so a crash never leaves it half written
+    pub fn save(&self, dir: &str) -> io::Result<()> {
+        let path = Self::path(dir);
+        let tmp = path.with_extension("json.part");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp, path)
+    }
+
+    /// whether the page was downloaded and its file is still intact
+    pub fn is_done(&self, index: usize, dir: &str) -> bool {
+        let Some(entry) = self.pages.get(&index) else {
+            return false;
+        };
+        if entry.status != PageStatus::Done {
+            return false;
+        }
+
+        match fs::read(Path::new(dir).join(&entry.file)) {
+            Ok(bytes) => entry.sha256.as_deref() == Some(sha256_hex(&bytes).as_str()),
+            Err(_) => false,
+        }
+    }
+
+    /// whether the page is marked as downloaded, without checking its file
+    pub fn is_marked_done(&self, index: usize) -> bool {
+        self.pages
+            .get(&index)
+            .is_some_and(|entry| entry.status == PageStatus::Done)
+    }
+
+    pub fn mark_done(&mut self, index: usize, file: String, bytes: &[u8]) {
+        let entry = PageEntry {
+            file,
+            status: PageStatus::Done,
+            size: Some(bytes.len() as u64),
+            sha256: Some(sha256_hex(bytes)),
+            error: None,
+        };
+        self.pages.insert(index, entry);
+    }
+
+    pub fn mark_failed(&mut self, index: usize, error: String) {
+        let entry = PageEntry {
+            // nothing was written, and the extension isn't known before the page is
+            file: String::new(),
+            status: PageStatus::Failed,
+            size: None,
+            sha256: None,
+            error: Some(error),
+        };
+        self.pages.insert(index, entry);
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn resume_skips_intact_pages() -> io::Result<()> {
+        let dir = TempDir::new("manifest");
+        let dir_str = dir.path_str();
+
+        let mut manifest = Manifest::new("https://rawmanga.net/manga/x/di-1hua", 3);
+        fs::write(dir.join("0.jpg"), b"page 0")?;
+        manifest.mark_done(0, "0.jpg".to_string(), b"page 0");
+        fs::write(dir.join("1.jpg"), b"page 1")?;
+        manifest.mark_done(1, "1.jpg".to_string(), b"page 1");
+        manifest.mark_failed(2, "timeout".to_string());
+        manifest.save(&dir_str)?;
+
+        // a page changed on disk is downloaded again
+        fs::write(dir.join("1.jpg"), b"truncated")?;
+
+        let loaded = Manifest::load(&dir_str)?.expect("manifest was saved");
+        assert_eq!(loaded, manifest);
+        assert!(loaded.is_done(0, &dir_str));
+        assert!(!loaded.is_done(1, &dir_str));
+        assert!(!loaded.is_done(2, &dir_str));
+        assert_eq!(loaded.pages[&2].file, "");
+        Ok(())
+    }
+}
@@ -0,0 +1,300 @@
+//! Folder names of downloaded chapters.
+//!
+//! Chapters are named after their url's title unless a `--name` template is given,
+//! ie. `{series}/{chapter:04}` downloads into `<root>/vagabond-4/0006`.
+//! Every name is made safe to use as a file name on windows, macos and linux,
+//! and a url without a title gets a name derived from the url itself.
+use std::str::FromStr;
+
+use reqwest::Url as ReqUrl;
+
+use crate::{manifest::sha256_hex, site::parse_chapter_number, url::Url};
+
+/// longest name kept, in bytes; most file systems stop at 255
+const MAX_NAME_LEN: usize = 200;
+
+/// names windows reserves for devices, with or without an extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A `--name` template, ie. `{series}/{chapter:04}`.
+///
+/// `/` separates folders; the placeholders are `{series}`, `{chapter}`, `{language}`,
+/// `{site}` and `{title}`. `{chapter:N}` pads the chapter number with zeros to N digits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameTemplate(Vec<Part>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Series,
+    Chapter { width: usize },
+    Language,
+    Site,
+    Title,
+}
+
+impl Part {
+    fn parse(field: &str) -> Result<Self, String> {
+        let (name, spec) = match field.split_once(':') {
+            Some((name, spec)) => (name.trim(), Some(spec.trim())),
+            None => (field.trim(), None),
+        };
+
+        let part = match name {
+            "series" => Part::Series,
+            "language" => Part::Language,
+            "site" => Part::Site,
+            "title" => Part::Title,
+            "chapter" => {
+                let width = spec
+                    .map(|spec| {
+                        spec.parse::<usize>().map_err(|_| {
+                            format!("`{{chapter:{spec}}}` needs a width, ie. `{{chapter:04}}`")
+                        })
+                    })
+                    .transpose()?;
+                return Ok(Part::Chapter {
+                    width: width.unwrap_or(0),
+                });
+            }
+            _ => {
+                return Err(format!(
+                    "unknown placeholder `{{{field}}}`, \
+                     expected {{series}}, {{chapter}}, {{language}}, {{site}} or {{title}}"
+                ))
+            }
+        };
+        match spec {
+            Some(_) => Err(format!("only {{chapter}} takes a width, not `{{{field}}}`")),
+            None => Ok(part),
+        }
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find(['{', '}']) {
+            let (text, field) = rest.split_at(start);
+            if field.starts_with('}') {
+                return Err(format!("unmatched `}}` in `{s}`"));
+            }
+            let end = field
+                .find('}')
+                .ok_or_else(|| format!("unclosed `{{` in `{s}`"))?;
+            if !text.is_empty() {
+                parts.push(Part::Text(text.to_string()));
+            }
+            parts.push(Part::parse(&field[1..end])?);
+            rest = &field[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        // without them every chapter of a series would be written into the same folder
+        if !parts
+            .iter()
+            .any(|part| matches!(part, Part::Chapter { .. } | Part::Title))
+        {
+            return Err(format!(
+                "`{s}` needs a {{chapter}} or {{title}} placeholder"
+            ));
+        }
+
+        Ok(NameTemplate(parts))
+    }
+}
+
+impl NameTemplate {
+    /// the folder of a chapter url relative to `--root`, split into folders at every `/`.
+    ///
+    /// `series` and `number` are used when the site can't tell them from the url;
+    /// a chapter without a number is named after its title.
+    pub fn render(&self, url: &Url, series: Option<&str>, number: Option<f32>) -> String {
+        let title = folder_name(url);
+        let series = url
+            .site
+            .series(&url.url)
+            .or_else(|| series.map(String::from))
+            .unwrap_or_else(|| title.clone());
+        let number = number.or_else(|| parse_chapter_number(&url.url));
+
+        let name: String = self
+            .0
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Series => sanitize(&series),
+                Part::Chapter { width } => match number {
+                    Some(number) => format_chapter(number, *width),
+                    None => title.clone(),
+                },
+                Part::Language => url
+                    .site
+                    .language(&url.url)
+                    .map_or_else(|| "unknown".to_string(), |lang| sanitize(&lang)),
+                Part::Site => sanitize(url.site.name()),
+                Part::Title => title.clone(),
+            })
+            .collect();
+
+        let folders: Vec<String> = name
+            .split('/')
+            .map(sanitize)
+            .filter(|folder| !folder.is_empty())
+            .collect();
+        match folders.is_empty() {
+            true => fallback_name(&url.url),
+            false => folders.join("/"),
+        }
+    }
+
+    /// the url with the template rendered as its title, see [`NameTemplate::render`]
+    pub fn apply(&self, url: &Url, series: Option<&str>, number: Option<f32>) -> Url {
+        Url {
+            title: Some(self.render(url, series, number)),
+            ..url.clone()
+        }
+    }
+}
+
+/// the folder a url is downloaded into: its sanitized title,
+/// or a name derived from the url when it has none
+pub fn folder_name(url: &Url) -> String {
+    url.title
+        .as_deref()
+        .map(|title| {
+            title
+                .split('/')
+                .map(sanitize)
+                .filter(|folder| !folder.is_empty())
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| fallback_name(&url.url))
+}
+
+/// `<host>-<hash of the url>`, so the same url is always downloaded into the same folder
+pub fn fallback_name(url: &str) -> String {
+    let host = ReqUrl::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(sanitize))
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "manga".to_string());
+
+    format!("{host}-{}", &sha256_hex(url.as_bytes())[..12])
+}
+
+/// makes a single file name safe on every platform.
+///
+/// characters windows doesn't allow become `_`, trailing dots and spaces are dropped,
+/// reserved device names get a `_` prefix and long names are cut at 200 bytes.
+/// the result is empty if nothing usable is left.
+pub fn sanitize(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    let mut name = replaced.trim_start();
+    if name.len() > MAX_NAME_LEN {
+        let mut end = MAX_NAME_LEN;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name = &name[..end];
+    }
+    let name = name.trim_end_matches(['.', ' ']);
+
+    let stem = name.split('.').next().unwrap_or_default();
+    match RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        true => format!("_{name}"),
+        false => name.to_string(),
+    }
+}
+
+/// the chapter number with its whole part padded to `width` digits, ie. `0010.5`
+fn format_chapter(number: f32, width: usize) -> String {
+    let text = number.to_string();
+    match text.split_once('.') {
+        Some((whole, fraction)) => format!("{whole:0>width$}.{fraction}"),
+        None => format!("{text:0>width$}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitized_names() {
+        assert_eq!(sanitize("Vagabond: Vol. 1?"), "Vagabond_ Vol. 1_");
+        assert_eq!(sanitize("a/b\\c|d*e\"f<g>h"), "a_b_c_d_e_f_g_h");
+        assert_eq!(sanitize("  chapter 6. . "), "chapter 6");
+        assert_eq!(sanitize("con"), "_con");
+        assert_eq!(sanitize("LPT1.txt"), "_LPT1.txt");
+        assert_eq!(sanitize("console"), "console");
+        assert_eq!(sanitize("..."), "");
+        assert_eq!(sanitize("tab\there"), "tab_here");
+
+        let long = "あ".repeat(100);
+        let cut = sanitize(&long);
+        assert!(cut.len() <= MAX_NAME_LEN);
+        assert!(long.starts_with(&cut));
+    }
+
+    #[test]
+    fn fallback_names_are_deterministic() {
+        let url = "https://mangagun.net/gunchap-1-shmg-x-raw.html";
+        assert_eq!(fallback_name(url), fallback_name(url));
+        assert!(fallback_name(url).starts_with("mangagun.net-"));
+        assert_ne!(
+            fallback_name(url),
+            fallback_name("https://mangagun.net/gunchap-2-shmg-x-raw.html")
+        );
+    }
+
+    #[test]
+    fn templates() -> Result<(), String> {
+        let url = Url::from_str("https://mangareader.to/read/vagabond-4/ja/chapter-6")
+            .map_err(|e| e.to_string())?;
+
+        let name = |template: &str| -> Result<String, String> {
+            Ok(NameTemplate::from_str(template)?.render(&url, None, None))
+        };
+        assert_eq!(name("{series}/{chapter:04}")?, "vagabond-4/0006");
+        assert_eq!(name("{site}/{language}/{chapter}")?, "MangaReader/ja/6");
+        assert_eq!(name("{title}")?, "vagabond-4-ja-chapter-6");
+        assert_eq!(name("../{series}//ch {chapter:3}")?, "vagabond-4/ch 006");
+        assert_eq!(
+            NameTemplate::from_str("{series}/{chapter:04}")?.render(&url, None, Some(10.5)),
+            "vagabond-4/0010.5"
+        );
+
+        for invalid in [
+            "{series}",
+            "{chapter",
+            "chapter}",
+            "{volume}/{chapter}",
+            "{series:04}/{chapter}",
+            "{chapter:x}",
+        ] {
+            assert!(NameTemplate::from_str(invalid).is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+//! Everything that shapes a download, independent of how it was asked for.
+use std::{fmt, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+
+use crate::{
+    convert::ConvertFormat, naming::NameTemplate, output::OutputFormat, report::ChapterReport,
+    retry::RetryPolicy, shutdown::Shutdown, trim::TrimOptions,
+};
+
+/// How [`crate::download()`] and [`crate::retry()`] download their urls.
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// directory every url is downloaded into
+    pub root: String,
+    /// only downloads chapters in this range from series urls
+    pub chapters: Option<ChapterRange>,
+    /// only downloads these pages of each chapter
+    pub pages: Option<PageRanges>,
+    /// names chapter folders relative to `root` instead of after the url's title
+    pub name: Option<NameTemplate>,
+    /// skips pages the chapter's manifest marks as downloaded
+    pub resume: bool,
+    /// how many images to fetch at once
+    pub concurrency: usize,
+    pub retry: RetryPolicy,
+    /// timeout of a single image request
+    pub timeout: Duration,
+    pub output: OutputFormat,
+    /// packs all chapters of a series url into a single file, if the output format can
+    pub bundle: bool,
+    pub convert_to: Option<ConvertFormat>,
+    pub trim: Option<TrimOptions>,
+    /// where pages that ran out of attempts are appended, see [`crate::failure_log`]
+    pub error_log: Option<PathBuf>,
+    /// geckodriver executable, looked up on the PATH if `None`
+    pub geckodriver: Option<PathBuf>,
+    /// firefox executable, looked up on the PATH and in the default install location if `None`
+    pub firefox: Option<PathBuf>,
+    /// url of a WebDriver server that's already running, used instead of starting geckodriver
+    pub webdriver: Option<String>,
+    pub log: LogLevel,
+    pub on_progress: Option<ProgressCallback>,
+    /// stops the run early; clones of the options share it, so give each run its own
+    /// to stop them separately
+    pub shutdown: Shutdown,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            root: "./download".to_string(),
+            chapters: None,
+            pages: None,
+            name: None,
+            resume: false,
+            concurrency: 4,
+            retry: RetryPolicy::default(),
+            timeout: Duration::from_millis(2500),
+            output: OutputFormat::default(),
+            bundle: false,
+            convert_to: None,
+            trim: None,
+            error_log: None,
+            geckodriver: None,
+            firefox: None,
+            webdriver: None,
+            log: LogLevel::Normal,
+            on_progress: None,
+            shutdown: Shutdown::default(),
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// passes an event to the `on_progress` callback, if there is one
+    pub(crate) fn emit(&self, event: ProgressEvent) {
+        if let Some(callback) = &self.on_progress {
+            callback.0(&event);
+        }
+    }
+}
+
+/// Something that happened during a download, passed to [`DownloadOptions::on_progress`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ProgressEvent {
+    /// the chapters of a series url were listed, after [`DownloadOptions::chapters`]
+    SeriesListed { url: String, chapters: usize },
+    /// the pages of a chapter were listed, `pages` of them are about to be fetched
+    ChapterStarted {
+        url: String,
+        title: String,
+        pages: usize,
+    },
+    /// a page was written to `path`
+    PageWritten {
+        url: String,
+        index: usize,
+        path: PathBuf,
+    },
+    /// a page ran out of attempts
+    PageFailed {
+        url: String,
+        index: usize,
+        error: String,
+    },
+    /// every page of a chapter was tried
+    ChapterFinished(ChapterReport),
+}
+
+/// Called with every [`ProgressEvent`] of a download, from the task that downloads it.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(&ProgressEvent) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new(callback: impl Fn(&ProgressEvent) + Send + Sync + 'static) -> Self {
+        ProgressCallback(Arc::new(callback))
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// inclusive range of chapter numbers passed to --chapters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChapterRange {
+    pub start: Option<f32>,
+    pub end: Option<f32>,
+}
+
+/// page numbers passed to --pages, counted from 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageRanges(Vec<(usize, Option<usize>)>);
+
+impl ChapterRange {
+    pub fn contains(&self, number: f32) -> bool {
+        self.start.is_none_or(|start| number >= start) && self.end.is_none_or(|end| number <= end)
+    }
+}
+
+impl FromStr for ChapterRange {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| -> Result<Option<f32>, String> {
+            let n = n.trim();
+            if n.is_empty() {
+                return Ok(None);
+            }
+            n.parse::<f32>()
+                .map(Some)
+                .map_err(|_| format!("`{n}` is not a chapter number"))
+        };
+
+        let (start, end) = match s.split_once("..") {
+            Some((start, end)) => (parse(start)?, parse(end.trim_start_matches('='))?),
+            None => {
+                let n = parse(s)?.ok_or("empty chapter range")?;
+                (Some(n), Some(n))
+            }
+        };
+        if let (Some(start), Some(end)) = (start, end) {
+            if start > end {
+                return Err(format!("`{}` ends before it starts", s.trim()));
+            }
+        }
+
+        Ok(ChapterRange { start, end })
+    }
+}
+
+impl PageRanges {
+    pub fn contains(&self, number: usize) -> bool {
+        self.0
+            .iter()
+            .any(|&(start, end)| number >= start && end.is_none_or(|end| number <= end))
+    }
+}
+
+impl fmt::Display for PageRanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges: Vec<String> = self
+            .0
+            .iter()
+            .map(|&(start, end)| match end {
+                Some(end) if end == start => start.to_string(),
+                Some(end) => format!("{start}-{end}"),
+                None => format!("{start}-"),
+            })
+            .collect();
+        write!(f, "{}", ranges.join(","))
+    }
+}
+
+impl FromStr for PageRanges {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| -> Result<usize, String> {
+            match n.trim().parse::<usize>() {
+                Ok(0) => Err("pages are counted from 1".to_string()),
+                Ok(n) => Ok(n),
+                Err(_) => Err(format!("`{}` is not a page number", n.trim())),
+            }
+        };
+
+        let mut ranges = Vec::new();
+        for part in s.split(',') {
+            let range = match part.split_once('-') {
+                Some((start, end)) if end.trim().is_empty() => (parse(start)?, None),
+                Some((start, end)) => (parse(start)?, Some(parse(end)?)),
+                None => {
+                    let n = parse(part)?;
+                    (n, Some(n))
+                }
+            };
+            if let (start, Some(end)) = range {
+                if start > end {
+                    return Err(format!("`{}` ends before it starts", part.trim()));
+                }
+            }
+            ranges.push(range);
+        }
+
+        Ok(PageRanges(ranges))
+    }
+}
+
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogLevel {
+    Normal,
+    Full,
+    Verbose,
+    /// nothing is printed, only the progress callback and the report tell what happened
+    Quiet,
+}
+
+impl LogLevel {
+    pub fn is_quiet(&self) -> bool {
+        self == &LogLevel::Quiet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chapter_ranges() {
+        let range = ChapterRange::from_str("10..25").unwrap();
+        assert!(range.contains(10.0) && range.contains(25.0) && range.contains(12.5));
+        assert!(!range.contains(9.0) && !range.contains(25.5));
+
+        let open = ChapterRange::from_str("10..").unwrap();
+        assert!(open.contains(1000.0) && !open.contains(9.9));
+
+        let single = ChapterRange::from_str("12").unwrap();
+        assert!(single.contains(12.0) && !single.contains(13.0));
+
+        assert!(ChapterRange::from_str("a..b").is_err());
+        assert!(ChapterRange::from_str("25..10").is_err());
+    }
+
+    #[test]
+    fn page_ranges() {
+        let pages = PageRanges::from_str("1-5,9,12-").unwrap();
+        assert!([1, 3, 5, 9, 12, 100].iter().all(|&n| pages.contains(n)));
+        assert!([6, 8, 10, 11].iter().all(|&n| !pages.contains(n)));
+        assert_eq!(pages.to_string(), "1-5,9,12-");
+
+        assert!(PageRanges::from_str("0").is_err());
+        assert!(PageRanges::from_str("5-2").is_err());
+        assert!(PageRanges::from_str("1,,3").is_err());
+    }
+}
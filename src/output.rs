@@ -0,0 +1,260 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    comicinfo::COMIC_INFO_FILE,
+    manifest::{Manifest, PageStatus},
+};
+
+/// How a downloaded chapter ends up on disk.
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// loose image files in the chapter folder
+    #[default]
+    Dir,
+    /// a comic book zip archive next to where the chapter folder would be
+    Cbz,
+    /// a fixed-layout EPUB 3 for e-readers
+    Epub,
+    /// a PDF with one page per image
+    Pdf,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            OutputFormat::Dir => None,
+            OutputFormat::Cbz => Some("cbz"),
+            OutputFormat::Epub => Some("epub"),
+            OutputFormat::Pdf => Some("pdf"),
+        }
+    }
+
+    /// whether a whole series can be bundled into a single file with --bundle
+    pub fn bundles(&self) -> bool {
+        matches!(self, OutputFormat::Epub | OutputFormat::Pdf)
+    }
+}
+
+/// The pages of a downloaded chapter folder, in reading order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChapterPages {
+    pub title: String,
+    pub dir: PathBuf,
+    /// file names inside `dir`
+    pub files: Vec<String>,
+    pub source_url: String,
+}
+
+impl ChapterPages {
+    pub fn from_manifest(title: &str, dl_path: &str, manifest: &Manifest) -> Self {
+        let files = manifest
+            .pages
+            .values()
+            .filter(|entry| entry.status == PageStatus::Done)
+            .map(|entry| entry.file.clone())
+            .collect();
+
+        ChapterPages {
+            title: title.to_string(),
+            dir: PathBuf::from(dl_path),
+            files,
+            source_url: manifest.source_url.clone(),
+        }
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.files.iter().map(|file| self.dir.join(file))
+    }
+}
+
+/// path of the file a chapter (or series) folder is packed into
+pub fn packed_path(dl_path: &str, format: OutputFormat) -> Option<PathBuf> {
+    let ext = format.extension()?;
+    Some(PathBuf::from(format!(
+        "{}.{ext}",
+        dl_path.trim_end_matches('/')
+    )))
+}
+
+/// extension of a page file, `jpg` if it has none
+pub fn page_extension(file: &Path) -> &str {
+    file.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("jpg")
+}
+
+/// writes a file through a `.part` file that's only renamed to `path` once complete,
+/// so a crash never leaves a half-valid file behind.
+///
+/// `build` gets the part file and hands it back once everything is written.
+pub fn write_file_atomic(
+    path: &Path,
+    build: impl FnOnce(File) -> io::Result<File>,
+) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".part");
+    let tmp = PathBuf::from(tmp);
+
+    let file = match build(File::create(&tmp)?) {
+        Ok(file) => file,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+    };
+    let res = file.sync_all().and_then(|_| {
+        drop(file);
+        fs::rename(&tmp, path)
+    });
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    res
+}
+
+/// writes a page image atomically, see [`write_file_atomic`]
+pub fn write_page(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    write_file_atomic(path, |mut file| {
+        file.write_all(bytes)?;
+        Ok(file)
+    })
+}
+
+/// builds a zip archive atomically, see [`write_file_atomic`]
+pub fn write_zip_atomic(
+    path: &Path,
+    build: impl FnOnce(&mut ZipWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    write_file_atomic(path, |file| {
+        let mut zip = ZipWriter::new(file);
+        build(&mut zip)?;
+        Ok(zip.finish()?)
+    })
+}
+
+/// packs the downloaded pages of a chapter folder into `<dl_path>.cbz`.
+///
+/// entries are named after their position, zero-padded so every reader sorts them the same.
+/// the chapter's `ComicInfo.xml` is embedded when there is one.
+pub fn write_cbz(chapter: &ChapterPages, path: &Path) -> io::Result<()> {
+    let width = chapter.files.len().to_string().len().max(3);
+    let comic_info = chapter.dir.join(COMIC_INFO_FILE);
+
+    // pages are already compressed images, deflating them again only costs time
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    write_zip_atomic(path, |zip| {
+        if comic_info.exists() {
+            zip.start_file(COMIC_INFO_FILE, options)?;
+            zip.write_all(&fs::read(&comic_info)?)?;
+        }
+        for (i, page) in chapter.paths().enumerate() {
+            let ext = page_extension(&page);
+            zip.start_file(format!("{:0width$}.{ext}", i + 1), options)?;
+            zip.write_all(&fs::read(&page)?)?;
+        }
+        Ok(())
+    })
+}
+
+/// escapes text for use inside xml elements and attributes
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// current UTC date and time as (year, month, day, hour, minute, second)
+pub fn utc_now() -> (i64, u32, u32, u32, u32, u32) {
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // civil_from_days: http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (
+        year,
+        month,
+        day,
+        (rem / 3600) as u32,
+        (rem % 3600 / 60) as u32,
+        (rem % 60) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    #[test]
+    fn cbz_entries_are_padded_and_ordered() -> Result<(), Box<dyn std::error::Error>> {
+        // the archive is written next to the chapter folder, so both are inside the temp dir
+        let root = TempDir::new("cbz");
+        let dir = root.join("di-1hua");
+        fs::create_dir_all(&dir)?;
+        let dl_path = dir.to_string_lossy().to_string();
+
+        let mut manifest = Manifest::new("https://rawmanga.net/manga/x/di-1hua", 11);
+        // written out of order like concurrent fetches would
+        for i in (0..11).rev() {
+            let file = format!("{i}.jpg");
+            let bytes = format!("page {i}");
+            fs::write(dir.join(&file), &bytes)?;
+            manifest.mark_done(i, file, bytes.as_bytes());
+        }
+
+        let chapter = ChapterPages::from_manifest("di-1hua", &dl_path, &manifest);
+        let path = packed_path(&dl_path, OutputFormat::Cbz).unwrap();
+        write_cbz(&chapter, &path)?;
+        assert!(!path.with_extension("cbz.part").exists());
+
+        let mut archive = ZipArchive::new(File::open(&path)?)?;
+        let names: Vec<String> = archive.file_names().map(String::from).collect();
+        let mut expected: Vec<String> = (1..=11).map(|i| format!("{i:03}.jpg")).collect();
+        assert_eq!(names, expected);
+
+        let mut last = String::new();
+        archive
+            .by_name(&expected.pop().unwrap())?
+            .read_to_string(&mut last)?;
+        assert_eq!(last, "page 10");
+        Ok(())
+    }
+
+    #[test]
+    fn xml_is_escaped() {
+        assert_eq!(
+            escape_xml(r#"Tom & Jerry <"1">'s"#),
+            "Tom &amp; Jerry &lt;&quot;1&quot;&gt;&apos;s"
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::output::{write_file_atomic, ChapterPages};
+
+/// an image ready to be embedded as an image XObject
+struct PdfImage {
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+    filter: &'static str,
+    data: Vec<u8>,
+}
+
+impl PdfImage {
+    /// jpegs are embedded as they are, everything else is decoded and deflated
+    fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        if let Some((width, height, components)) = jpeg_info(&bytes) {
+            // cmyk jpegs are often stored inverted, so they're re-encoded like the rest
+            let color_space = match components {
+                1 => Some("/DeviceGray"),
+                3 => Some("/DeviceRGB"),
+                _ => None,
+            };
+            if let Some(color_space) = color_space {
+                return Ok(PdfImage {
+                    width,
+                    height,
+                    color_space,
+                    filter: "/DCTDecode",
+                    data: bytes,
+                });
+            }
+        }
+
+        let img = image::load_from_memory(&bytes)
+            .map_err(io::Error::other)?
+            .to_rgba8();
+        let (width, height) = img.dimensions();
+        // pdf images have no alpha channel, so transparent pixels are blended onto white
+        let mut rgb = Vec::with_capacity(width as usize * height as usize * 3);
+        for px in img.pixels() {
+            let [r, g, b, a] = px.0;
+            for c in [r, g, b] {
+                let c = (u16::from(c) * u16::from(a) + 255 * (255 - u16::from(a))) / 255;
+                rgb.push(c as u8);
+            }
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&rgb)?;
+
+        Ok(PdfImage {
+            width,
+            height,
+            color_space: "/DeviceRGB",
+            filter: "/FlateDecode",
+            data: encoder.finish()?,
+        })
+    }
+}
+
+/// reads the size and color component count from a jpeg's start-of-frame marker
+fn jpeg_info(bytes: &[u8]) -> Option<(u32, u32, u8)> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let be16 = |i: usize| -> Option<usize> {
+        Some(usize::from(*bytes.get(i)?) << 8 | usize::from(*bytes.get(i + 1)?))
+    };
+
+    let mut i = 2;
+    while i + 1 < bytes.len() {
+        if bytes[i] != 0xFF {
+            return None;
+        }
+        let marker = bytes[i + 1];
+        match marker {
+            // fill bytes
+            0xFF => i += 1,
+            0xD8 | 0x01 | 0xD0..=0xD7 => i += 2,
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                let height = be16(i + 5)? as u32;
+                let width = be16(i + 7)? as u32;
+                let components = *bytes.get(i + 9)?;
+                return Some((width, height, components));
+            }
+            _ => i += 2 + be16(i + 2)?,
+        }
+    }
+
+    None
+}
+
+/// encodes text as a utf-16 pdf string so any title survives
+fn pdf_text(text: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        hex.push_str(&format!("{unit:04X}"));
+    }
+    hex.push('>');
+    hex
+}
+
+/// writes numbered objects while remembering their offsets for the xref table
+struct PdfWriter<W: Write> {
+    out: W,
+    offset: u64,
+    /// offset of object `n` at index `n - 1`
+    offsets: Vec<u64>,
+}
+
+impl<W: Write> PdfWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn object(&mut self, id: usize, body: &str) -> io::Result<()> {
+        self.offsets[id - 1] = self.offset;
+        self.write(format!("{id} 0 obj\n{body}\nendobj\n").as_bytes())
+    }
+
+    fn stream(&mut self, id: usize, dict: &str, data: &[u8]) -> io::Result<()> {
+        self.offsets[id - 1] = self.offset;
+        self.write(
+            format!("{id} 0 obj\n<< {dict} /Length {} >>\nstream\n", data.len()).as_bytes(),
+        )?;
+        self.write(data)?;
+        self.write(b"\nendstream\nendobj\n")
+    }
+
+    fn finish(mut self, root: usize, info: usize) -> io::Result<W> {
+        let xref = self.offset;
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            table.push_str(&format!("{offset:010} 00000 n \n"));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root {root} 0 R /Info {info} 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            self.offsets.len() + 1
+        ));
+        self.write(table.as_bytes())?;
+
+        Ok(self.out)
+    }
+}
+
+/// writes the pages of one or more chapters into a pdf at `path`, one page per image
+/// at its native size.
+///
+/// pages are read one at a time, so memory use doesn't grow with the chapter.
+/// when several chapters are bundled, each gets a bookmark pointing to its first page.
+pub fn write_pdf(path: &Path, title: &str, chapters: &[ChapterPages]) -> io::Result<()> {
+    // a bookmark needs a page to point to
+    let chapters: Vec<&ChapterPages> = chapters.iter().filter(|c| !c.files.is_empty()).collect();
+    let page_count: usize = chapters.iter().map(|c| c.files.len()).sum();
+    if page_count == 0 {
+        return Err(io::Error::other("no pages to write to the pdf"));
+    }
+
+    // 1: catalog, 2: page tree, then a page, its contents and its image for every page,
+    // followed by the outline root, one outline item per chapter and the info dictionary
+    const CATALOG: usize = 1;
+    const PAGES: usize = 2;
+    let page_id = |n: usize| 3 + 3 * n;
+    let outlines = page_id(page_count);
+    let bookmarks = chapters.len() > 1;
+    let info = outlines + chapters.len() + 1;
+
+    write_file_atomic(path, |file| {
+        let mut pdf = PdfWriter {
+            out: BufWriter::new(file),
+            offset: 0,
+            offsets: vec![0; info],
+        };
+        pdf.write(b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n")?;
+
+        let mut n = 0;
+        let mut first_pages = Vec::with_capacity(chapters.len());
+        for chapter in &chapters {
+            first_pages.push(page_id(n));
+            for page in chapter.paths() {
+                let img = PdfImage::load(&page)?;
+                let (id, contents, image) = (page_id(n), page_id(n) + 1, page_id(n) + 2);
+
+                pdf.object(
+                    id,
+                    &format!(
+                        "<< /Type /Page /Parent {PAGES} 0 R /MediaBox [0 0 {w} {h}] \
+                         /Resources << /XObject << /Im0 {image} 0 R >> >> /Contents {contents} 0 R >>",
+                        w = img.width,
+                        h = img.height,
+                    ),
+                )?;
+                let draw = format!("q {} 0 0 {} 0 0 cm /Im0 Do Q", img.width, img.height);
+                pdf.stream(contents, "", draw.as_bytes())?;
+                pdf.stream(
+                    image,
+                    &format!(
+                        "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} \
+                         /BitsPerComponent 8 /Filter {}",
+                        img.width, img.height, img.color_space, img.filter
+                    ),
+                    &img.data,
+                )?;
+                n += 1;
+            }
+        }
+
+        let kids: Vec<String> = (0..page_count)
+            .map(|n| format!("{} 0 R", page_id(n)))
+            .collect();
+        pdf.object(
+            PAGES,
+            &format!(
+                "<< /Type /Pages /Kids [{}] /Count {page_count} >>",
+                kids.join(" ")
+            ),
+        )?;
+
+        let item = |c: usize| outlines + 1 + c;
+        pdf.object(
+            outlines,
+            &format!(
+                "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+                item(0),
+                item(chapters.len() - 1),
+                chapters.len()
+            ),
+        )?;
+        for (c, chapter) in chapters.iter().enumerate() {
+            let mut body = format!(
+                "<< /Title {} /Parent {outlines} 0 R /Dest [{} 0 R /Fit]",
+                pdf_text(&chapter.title),
+                first_pages[c]
+            );
+            if c > 0 {
+                body.push_str(&format!(" /Prev {} 0 R", item(c - 1)));
+            }
+            if c + 1 < chapters.len() {
+                body.push_str(&format!(" /Next {} 0 R", item(c + 1)));
+            }
+            body.push_str(" >>");
+            pdf.object(item(c), &body)?;
+        }
+
+        // bundles open with the bookmarks shown, manga reads right to left
+        let page_mode = match bookmarks {
+            true => " /PageMode /UseOutlines",
+            false => "",
+        };
+        pdf.object(
+            CATALOG,
+            &format!(
+                "<< /Type /Catalog /Pages {PAGES} 0 R /Outlines {outlines} 0 R{page_mode} \
+                 /ViewerPreferences << /Direction /R2L >> >>"
+            ),
+        )?;
+        pdf.object(
+            info,
+            &format!("<< /Title {} /Producer (manga_dl) >>", pdf_text(title)),
+        )?;
+
+        let out = pdf.finish(CATALOG, info)?;
+        out.into_inner().map_err(|e| e.into_error())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::path::PathBuf;
+
+    #[test]
+    fn jpeg_passthrough_and_bookmarks() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new("pdf");
+        image::RgbImage::new(20, 30).save(dir.join("1.jpg"))?;
+        image::RgbaImage::new(40, 30).save(dir.join("2.png"))?;
+
+        let chapter = |title: &str, file: &str| ChapterPages {
+            title: title.to_string(),
+            dir: dir.0.clone(),
+            files: vec![file.to_string()],
+            source_url: String::new(),
+        };
+        let path: PathBuf = dir.join("series.pdf");
+        write_pdf(
+            &path,
+            "series",
+            &[chapter("chapter-1", "1.jpg"), chapter("chapter-2", "2.png")],
+        )?;
+
+        let pdf = fs::read(&path)?;
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.7"));
+        assert!(text.contains("/MediaBox [0 0 20 30]") && text.contains("/MediaBox [0 0 40 30]"));
+        assert!(text.contains("/Filter /DCTDecode") && text.contains("/Filter /FlateDecode"));
+        assert!(text.contains("/Outlines 9 0 R /PageMode /UseOutlines"));
+        // the jpeg is embedded byte for byte
+        let jpeg = fs::read(dir.join("1.jpg"))?;
+        assert!(pdf.windows(jpeg.len()).any(|w| w == jpeg.as_slice()));
+
+        // every xref entry points at the start of its object
+        let xref = pdf.windows(5).rposition(|w| w == b"xref\n").unwrap();
+        let table = std::str::from_utf8(&pdf[xref..])?;
+        for (id, line) in table.lines().skip(3).take(11).enumerate() {
+            let offset: usize = line[..10].parse()?;
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", id + 1).as_bytes()));
+        }
+        Ok(())
+    }
+}
@@ -1,114 +1,91 @@
-#[allow(unused_imports)]
-use std::{
-    collections::HashSet,
-    io::Write,
-    time::{Duration, Instant},
-};
-
-use color_eyre::{
-    eyre::{eyre, Context},
-    owo_colors::OwoColorize,
-    Result,
-};
-use fantoccini::{Client, Locator};
-use reqwest::ClientBuilder as ReqClientBuilder;
-use spinners::{Spinner, Spinners};
-//use spinners::{Spinner, Spinners};
+use async_trait::async_trait;
+use color_eyre::{owo_colors::OwoColorize, Result};
+use reqwest::Url as ReqUrl;
+use scraper::Html;
 
 use crate::{
-    cli::{Cli, LogLevel, Url},
-    g_handle_popup,
-    loading::{downloading_panel_data_msg, print_reqerr_count},
-    mangareader::{download_img_src, write_img, ReqImageData},
-    setup_nav,
+    error::ArgError,
+    http::{select_chapter_links, select_urls},
+    site::{host_is, path_segments, Chapter, Page, PageSource, SiteAdapter},
+    style_text,
 };
 
-pub async fn dl_rawmanga(client: &Client, url: &Url, args: &Cli) -> Result<()> {
-    let (title, dl_path, mut sp) = setup_nav(client, url, args).await?;
-    //let start = Instant::now();
+/// https://rawmanga.net/manga/zaziyoziyoranzu-the-jojolands/di-1hua
+#[derive(Debug)]
+pub struct RawManga;
+
+pub static RAWMANGA: RawManga = RawManga;
 
-    let index_map: Option<HashSet<&usize>> =
-        args.indexes.as_ref().map(|slice| slice.iter().collect());
+#[async_trait]
+impl SiteAdapter for RawManga {
+    fn name(&self) -> &'static str {
+        "RawManga"
+    }
 
-    let req_client = ReqClientBuilder::new().timeout(Duration::from_millis(2500));
-    let req_client = req_client.build()?;
+    fn detect(&self, url: &ReqUrl) -> bool {
+        host_is(url, "rawmanga.net")
+    }
 
-    let src_urls = get_all_image_srcs(&dl_path, client, index_map, &args.log).await?;
-    let mut img_data = Vec::with_capacity(src_urls.len());
-    let mut error_reports = Vec::with_capacity(src_urls.len());
+    fn validate(&self, url: &ReqUrl) -> Result<(), ArgError> {
+        let reason = match path_segments(url).as_slice() {
+            ["manga", _] | ["manga", _, _] => return Ok(()),
+            ["manga"] => "rawmanga URL is missing the series after /manga/",
+            _ => "rawmanga URL must be /manga/<series> or /manga/<series>/<chapter>",
+        };
 
-    let max = src_urls.len();
-    if !src_urls.is_empty() {
-        for (i, img) in src_urls.into_iter().enumerate() {
-            let res = download_img_src(&img.url, img.path, &req_client).await;
+        Err(ArgError::InvalidUrl {
+            url: url.to_string(),
+            reason: style_text!(reason),
+            example: style_text!("rawmanga.net/manga/zaziyoziyoranzu-the-jojolands/di-1hua"),
+        })
+    }
 
-            match res {
-                Ok(r) => img_data.push(r),
-                Err(e) => {
-                    let report = eyre!("failed on `{}. {}`: \n{}", i, img.url, e.to_string());
-                    error_reports.push(report);
-                }
-            }
-            let msg = downloading_panel_data_msg(i as u16, max as u16);
-            sp = Spinner::new(Spinners::Arc, msg);
+    fn title(&self, url: &str) -> Option<String> {
+        let url = ReqUrl::parse(url).ok()?;
+        match path_segments(&url).as_slice() {
+            ["manga", path @ ..] if !path.is_empty() => Some(path.join("_")),
+            _ => None,
         }
     }
-    sp.stop_with_newline();
 
-    img_data
-        .into_iter()
-        .for_each(|data| write_img(&data).unwrap());
+    /// series are `/manga/<title>`, chapters `/manga/<title>/<chapter>`
+    fn is_series(&self, url: &str) -> bool {
+        ReqUrl::parse(url).is_ok_and(|url| matches!(path_segments(&url).as_slice(), ["manga", _]))
+    }
 
-    if !error_reports.is_empty() {
-        print_reqerr_count(error_reports.len(), &title);
-    } else if !error_reports.is_empty() {
-        print_reqerr_count(error_reports.len(), &title);
-        println!("{}", "STDERROR:\n".bright_red());
-        for e in error_reports.into_iter() {
-            eprintln!("{}", e.red());
+    fn series(&self, url: &str) -> Option<String> {
+        let url = ReqUrl::parse(url).ok()?;
+        match path_segments(&url).as_slice() {
+            ["manga", series, ..] => Some(series.to_string()),
+            _ => None,
         }
     }
 
-    Ok(())
-}
+    /// raws are always japanese
+    fn language(&self, _url: &str) -> Option<String> {
+        Some("ja".to_string())
+    }
 
-async fn get_all_image_srcs(
-    dl_path: &str,
-    c: &Client,
-    index_map: Option<HashSet<&usize>>,
-    log: &LogLevel,
-) -> Result<HashSet<ReqImageData>> {
-    g_handle_popup(c).await.wrap_err(line!())?;
-    let imgs = c.find_all(Locator::Css("div.page-chapter img")).await?;
-    let mut new_imgs: HashSet<ReqImageData> = HashSet::new();
+    /// chapters are plain html, the images only need their src
+    fn needs_browser(&self) -> bool {
+        false
+    }
 
-    //let max = imgs.len();
-    // if the index map is Some, skip indexes that aren't specified
-    for (i, img) in imgs.into_iter().enumerate() {
-        if let Some(i_map) = &index_map {
-            if !i_map.contains(&i) {
-                continue;
-            }
-        }
-        if let Some(src) = img.attr("src").await? {
-            let path = format!("{dl_path}/{i}.jpg");
-            let img = ReqImageData { url: src, path };
-            match log {
-                LogLevel::Full | LogLevel::Verbose => println!("{:?}", img),
-                _ => {}
-            }
-            new_imgs.insert(img);
-        }
+    fn parse_chapters(&self, html: &Html, url: &str) -> Result<Vec<Chapter>> {
+        select_chapter_links(html, "ul.list-chapters a", url)
     }
 
-    Ok(new_imgs)
-}
+    fn parse_pages(&self, html: &Html, url: &str) -> Result<Vec<Page>> {
+        let srcs = select_urls(html, "div.page-chapter img", "src", url)?;
+        let pages = srcs
+            .into_iter()
+            .enumerate()
+            .map(|(i, src)| Page {
+                index: i,
+                source: PageSource::Src(src),
+            })
+            .collect();
 
-#[tokio::test]
-async fn test_reqwest() -> Result<(), Box<dyn std::error::Error>> {
-    let url = "https://lovejp-cdn.site/one-piece/chapter-999/001.jpg";
-    let bytes = reqwest::get(url).await?.bytes().await?;
-    std::fs::write("test.jpg", &bytes)?;
-    println!("Downloaded and saved test.jpg");
-    Ok(())
+        Ok(pages)
+    }
 }
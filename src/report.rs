@@ -0,0 +1,185 @@
+//! Exit codes and the `--report` summary of a run.
+use std::{
+    io::{self, BufWriter, Write},
+    path::Path,
+    process::ExitCode,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::output::write_file_atomic;
+
+/// How a run ended, exits with [`RunStatus::code`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    /// every page of every url was downloaded
+    Success,
+    /// some pages or urls failed, the rest was downloaded
+    PartialFailure,
+    /// the arguments or site definitions were invalid
+    BadArguments,
+    /// nothing could be downloaded
+    TotalFailure,
+    /// stopped by Ctrl-C or SIGTERM
+    Interrupted,
+}
+
+impl RunStatus {
+    pub fn code(&self) -> u8 {
+        match self {
+            RunStatus::Success => 0,
+            RunStatus::PartialFailure => 1,
+            RunStatus::BadArguments => 2,
+            RunStatus::TotalFailure => 3,
+            RunStatus::Interrupted => 130,
+        }
+    }
+}
+
+impl From<RunStatus> for ExitCode {
+    fn from(status: RunStatus) -> Self {
+        ExitCode::from(status.code())
+    }
+}
+
+/// A page that ran out of attempts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageFailure {
+    pub index: usize,
+    /// the image url, or the site name for pages captured from the browser
+    pub source: String,
+    /// name of the [`crate::error::DownloadImageError`] variant
+    pub variant: &'static str,
+    pub error: String,
+    pub attempts: u32,
+}
+
+/// What became of a single chapter.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChapterReport {
+    pub url: String,
+    pub title: String,
+    pub pages_expected: usize,
+    pub pages_written: usize,
+    pub failures: Vec<PageFailure>,
+}
+
+impl ChapterReport {
+    /// whether every expected page was written; a chapter without pages never is
+    pub fn is_complete(&self) -> bool {
+        self.pages_expected > 0 && self.pages_written >= self.pages_expected
+    }
+}
+
+/// What became of a url passed with `--urls`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UrlReport {
+    pub url: String,
+    pub title: Option<String>,
+    /// every chapter that was tried, one for a chapter url
+    pub chapters: Vec<ChapterReport>,
+    /// why the url (or some of its chapters) couldn't be downloaded
+    pub error: Option<String>,
+}
+
+/// The summary written with `--report`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunReport {
+    pub status: RunStatus,
+    pub exit_code: u8,
+    pub elapsed_secs: f64,
+    pub urls: Vec<UrlReport>,
+}
+
+impl RunReport {
+    pub fn new(urls: Vec<UrlReport>, elapsed: Duration, interrupted: bool) -> Self {
+        let status = match interrupted {
+            true => RunStatus::Interrupted,
+            false => run_status(&urls),
+        };
+
+        RunReport {
+            status,
+            exit_code: status.code(),
+            elapsed_secs: elapsed.as_secs_f64(),
+            urls,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_file_atomic(path, |file| {
+            let mut out = BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut out, self)?;
+            out.write_all(b"\n")?;
+            out.into_inner().map_err(|e| e.into_error())
+        })
+    }
+}
+
+fn run_status(urls: &[UrlReport]) -> RunStatus {
+    let chapters = || urls.iter().flat_map(|url| &url.chapters);
+    let failed = urls.iter().any(|url| url.error.is_some())
+        || chapters().any(|chapter| !chapter.is_complete());
+    let written = chapters().any(|chapter| chapter.pages_written > 0);
+
+    match (failed, written) {
+        (false, _) => RunStatus::Success,
+        (true, true) => RunStatus::PartialFailure,
+        (true, false) => RunStatus::TotalFailure,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(expected: usize, written: usize) -> ChapterReport {
+        ChapterReport {
+            url: "https://rawmanga.net/manga/x/di-1hua".to_string(),
+            title: "x_di-1hua".to_string(),
+            pages_expected: expected,
+            pages_written: written,
+            failures: Vec::new(),
+        }
+    }
+
+    fn url(chapters: Vec<ChapterReport>, error: Option<&str>) -> UrlReport {
+        UrlReport {
+            url: "https://rawmanga.net/manga/x".to_string(),
+            title: Some("x".to_string()),
+            chapters,
+            error: error.map(String::from),
+        }
+    }
+
+    #[test]
+    fn statuses() {
+        assert_eq!(
+            run_status(&[url(vec![chapter(3, 3)], None)]),
+            RunStatus::Success
+        );
+        assert_eq!(
+            run_status(&[url(vec![chapter(3, 3)], None), url(vec![], Some("404"))]),
+            RunStatus::PartialFailure
+        );
+        assert_eq!(
+            run_status(&[url(vec![chapter(3, 1)], None)]),
+            RunStatus::PartialFailure
+        );
+        assert_eq!(
+            run_status(&[url(vec![chapter(3, 0)], Some("timeout"))]),
+            RunStatus::TotalFailure
+        );
+        assert_eq!(
+            run_status(&[url(vec![chapter(0, 0)], None)]),
+            RunStatus::TotalFailure
+        );
+
+        let report = RunReport::new(vec![], Duration::from_millis(1500), true);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["status"], "interrupted");
+        assert_eq!(json["exit_code"], 130);
+    }
+}
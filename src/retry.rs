@@ -0,0 +1,204 @@
+use std::{
+    future::Future,
+    hash::{BuildHasher, RandomState},
+    time::{Duration, SystemTime},
+};
+
+use fantoccini::error::{CmdError, ErrorStatus};
+use reqwest::StatusCode;
+use thiserror::Error;
+use tokio::time::sleep;
+
+use crate::error::DownloadImageError;
+
+/// upper bound of a single backoff delay
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// How often and how patiently a failed page fetch or element wait is retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// total attempts, including the first one
+    pub max_attempts: u32,
+    /// delay before the first retry, doubled on every following one
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// randomize each delay between 50% and 100% so parallel fetches don't retry in lockstep
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: MAX_DELAY,
+            jitter: true,
+        }
+    }
+}
+
+/// the last error of an operation that ran out of attempts (or wasn't worth retrying)
+#[derive(Error, Debug)]
+#[error("{error} (after {attempts} attempt(s))")]
+pub struct RetryError<E> {
+    pub error: E,
+    pub attempts: u32,
+}
+
+/// Errors that know whether trying again could help.
+pub trait Retryable {
+    fn is_retryable(&self) -> bool;
+}
+
+impl RetryPolicy {
+    /// delay before the given retry (1 is the first retry)
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)));
+        let delay = exp.min(self.max_delay);
+
+        match self.jitter {
+            true => delay.mul_f64(0.5 + random_fraction() / 2.0),
+            false => delay,
+        }
+    }
+
+    /// runs `op` until it succeeds, fails with a non retryable error or runs out of attempts
+    pub async fn run<T, E, F, Fut>(&self, mut op: F) -> Result<T, RetryError<E>>
+    where
+        E: Retryable,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match op().await {
+                Ok(v) => return Ok(v),
+                Err(error) if attempts >= self.max_attempts || !error.is_retryable() => {
+                    return Err(RetryError { error, attempts });
+                }
+                Err(_) => sleep(self.delay(attempts)).await,
+            }
+        }
+    }
+}
+
+/// a number in [0, 1) that's random enough for jitter
+fn random_fraction() -> f64 {
+    let seed = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    let hash = RandomState::new().hash_one(seed);
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+impl Retryable for reqwest::Error {
+    fn is_retryable(&self) -> bool {
+        match self.status() {
+            Some(status) => is_retryable_status(status),
+            None => self.is_timeout() || self.is_connect() || self.is_request() || self.is_body(),
+        }
+    }
+}
+
+impl Retryable for CmdError {
+    /// elements that haven't rendered yet are worth waiting for again,
+    /// a dead session or a broken script isn't
+    fn is_retryable(&self) -> bool {
+        match self {
+            CmdError::WaitTimeout => true,
+            CmdError::Standard(e) => matches!(
+                e.error,
+                ErrorStatus::NoSuchElement
+                    | ErrorStatus::StaleElementReference
+                    | ErrorStatus::ElementNotInteractable
+                    | ErrorStatus::Timeout
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl Retryable for DownloadImageError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            DownloadImageError::Status(_, status) => is_retryable_status(*status),
+            DownloadImageError::GetReqwest(_, e) | DownloadImageError::Reqwest(e) => {
+                e.is_retryable()
+            }
+            DownloadImageError::Fantoccini(e) => e.is_retryable(),
+            // the canvas or image may not have loaded yet,
+            // or the server sent an error page or a placeholder in its place
+            DownloadImageError::InvalidDataUrl(_)
+            | DownloadImageError::InvalidImage(_)
+            | DownloadImageError::MissingCanvasElement(_)
+            | DownloadImageError::MissingImgElement(_) => true,
+            DownloadImageError::ColorEyre(_)
+            | DownloadImageError::Base64(_)
+            | DownloadImageError::CanvasScript(_)
+            | DownloadImageError::Image(_)
+            | DownloadImageError::Io(_)
+            | DownloadImageError::ReaderStuck(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn exponential_delays() {
+        let policy = RetryPolicy {
+            jitter: false,
+            max_delay: Duration::from_secs(3),
+            ..Default::default()
+        };
+        let delays: Vec<u128> = (1..=5).map(|r| policy.delay(r).as_millis()).collect();
+        assert_eq!(delays, [500, 1000, 2000, 3000, 3000]);
+
+        let jittered = RetryPolicy::default().delay(2);
+        assert!(jittered >= Duration::from_millis(500) && jittered <= Duration::from_millis(1000));
+    }
+
+    #[tokio::test]
+    async fn stops_on_non_retryable() {
+        let policy = RetryPolicy {
+            base_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let calls = Cell::new(0);
+
+        let not_found = policy
+            .run(|| async {
+                calls.set(calls.get() + 1);
+                Err::<(), _>(DownloadImageError::Status(
+                    "https://cdn/1.jpg".into(),
+                    StatusCode::NOT_FOUND,
+                ))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!((not_found.attempts, calls.get()), (1, 1));
+
+        let unavailable = policy
+            .run(|| async {
+                Err::<(), _>(DownloadImageError::Status(
+                    "https://cdn/1.jpg".into(),
+                    StatusCode::SERVICE_UNAVAILABLE,
+                ))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(unavailable.attempts, 3);
+    }
+}
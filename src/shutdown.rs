@@ -0,0 +1,168 @@
+//! Stopping a run early on Ctrl-C or SIGTERM without losing what was already downloaded.
+//!
+//! Every run is stopped through the [`Shutdown`] handle of its [`crate::DownloadOptions`].
+//! The first signal only asks the downloads to stop: no new page is started, the ones in
+//! flight are written and recorded in their manifest, and the browser is closed as usual.
+//! A second signal stops the run right away, still closing the browser and geckodriver
+//! and removing their temp directory. A third one exits without cleaning up.
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use color_eyre::{eyre::eyre, Result};
+use tokio::sync::Notify;
+
+/// Stops the runs it's passed to, see [`crate::DownloadOptions::shutdown`].
+///
+/// clones share their stop requests, so a run started with a clone is stopped with it.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown(Arc<State>);
+
+#[derive(Debug, Default)]
+struct State {
+    requested: AtomicBool,
+    quitting: AtomicBool,
+    /// woken by [`Shutdown::quit`]
+    quit: Notify,
+    /// number of runs in progress
+    running: AtomicUsize,
+}
+
+impl Shutdown {
+    /// asks the runs to stop after the pages in flight
+    pub fn request(&self) {
+        self.0.requested.store(true, Ordering::Relaxed);
+    }
+
+    /// whether the runs were asked to stop
+    pub fn requested(&self) -> bool {
+        self.0.requested.load(Ordering::Relaxed)
+    }
+
+    /// stops the runs right away; they end with an error
+    pub fn quit(&self) {
+        self.request();
+        self.0.quitting.store(true, Ordering::Relaxed);
+        self.0.quit.notify_waiters();
+    }
+
+    /// whether a run is in progress
+    pub fn is_running(&self) -> bool {
+        self.0.running.load(Ordering::Relaxed) > 0
+    }
+
+    /// runs `run` until it's done or [`Shutdown::quit`] is called.
+    ///
+    /// quitting drops `run`, so whatever it owns, like the browser session,
+    /// is closed before the run returns.
+    pub(crate) async fn cancellable<T>(&self, run: impl Future<Output = Result<T>>) -> Result<T> {
+        struct Running<'a>(&'a State);
+        impl Drop for Running<'_> {
+            fn drop(&mut self) {
+                self.0.running.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+        self.0.running.fetch_add(1, Ordering::Relaxed);
+        let _running = Running(&self.0);
+
+        tokio::select! {
+            res = run => res,
+            _ = self.quitting() => Err(eyre!("quit before the run was done")),
+        }
+    }
+
+    /// resolves once [`Shutdown::quit`] was called
+    async fn quitting(&self) {
+        // created first so a quit between the check and the wait isn't missed
+        let notified = self.0.quit.notified();
+        if !self.0.quitting.load(Ordering::Relaxed) {
+            notified.await;
+        }
+    }
+}
+
+/// resolves on the next Ctrl-C, or SIGTERM on unix
+async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// starts stopping the runs of `shutdown` on shutdown signals, in the background
+pub fn listen(shutdown: Shutdown) {
+    tokio::spawn(async move {
+        loop {
+            signal().await;
+            if !shutdown.requested() {
+                shutdown.request();
+                eprintln!("\nstopping after the pages in flight, press Ctrl-C again to quit now");
+                continue;
+            }
+            if shutdown.0.quitting.load(Ordering::Relaxed) || !shutdown.is_running() {
+                std::process::exit(130);
+            }
+            eprintln!("\nquitting, closing the browser first");
+            shutdown.quit();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn quitting_drops_the_run() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        struct Owned(Arc<AtomicBool>);
+        impl Drop for Owned {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let shutdown = Shutdown::default();
+        let owned = Owned(dropped.clone());
+        let run = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                shutdown
+                    .cancellable(async move {
+                        let _owned = owned;
+                        std::future::pending::<Result<()>>().await
+                    })
+                    .await
+            }
+        });
+        while !shutdown.is_running() {
+            tokio::task::yield_now().await;
+        }
+        // another handle's quit leaves the run alone
+        Shutdown::default().quit();
+        tokio::task::yield_now().await;
+        assert!(!run.is_finished());
+
+        shutdown.quit();
+        assert!(run.await.unwrap().is_err());
+        assert!(dropped.load(Ordering::Relaxed));
+        assert!(!shutdown.is_running());
+    }
+}
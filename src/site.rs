@@ -0,0 +1,352 @@
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Mutex, OnceLock,
+    },
+};
+
+use async_trait::async_trait;
+use color_eyre::{eyre::eyre, Result};
+use fantoccini::{Client, Locator};
+use regex::Regex;
+use reqwest::{Client as ReqClient, Url as ReqUrl};
+use scraper::Html;
+
+use crate::{
+    error::{ArgError, DownloadImageError},
+    mangagun::MANGAGUN,
+    mangareader::MANGAREADER,
+    options::DownloadOptions,
+    rawmanga::RAWMANGA,
+};
+
+/// Every site manga_dl supports out of the box.
+///
+/// Adding a site means writing a module that implements [`SiteAdapter`]
+/// and listing it here, or dropping a [`crate::definition::SiteDefinition`]
+/// into the sites directory.
+/// Todo:
+/// Try out mangaraw.ma
+static BUILTIN_ADAPTERS: [&dyn SiteAdapter; 3] = [&MANGAREADER, &MANGAGUN, &RAWMANGA];
+
+static ADAPTERS: OnceLock<Vec<&'static dyn SiteAdapter>> = OnceLock::new();
+
+/// registers the sites loaded from definition files.
+///
+/// they are checked before the builtin ones, so a definition can fix a broken builtin site.
+/// only the first call has an effect.
+pub fn register_adapters(sites: Vec<Box<dyn SiteAdapter>>) {
+    ADAPTERS.get_or_init(|| {
+        sites
+            .into_iter()
+            .map(|site| &*Box::leak(site))
+            .chain(BUILTIN_ADAPTERS)
+            .collect()
+    });
+}
+
+pub fn adapters() -> &'static [&'static dyn SiteAdapter] {
+    ADAPTERS.get().map_or(&BUILTIN_ADAPTERS, |a| a.as_slice())
+}
+
+/// returns the first adapter that recognizes the url
+pub fn find_adapter(url: &ReqUrl) -> Option<&'static dyn SiteAdapter> {
+    adapters().iter().copied().find(|a| a.detect(url))
+}
+
+/// whether the url's host is `domain` or one of its subdomains
+pub fn host_is(url: &ReqUrl, domain: &str) -> bool {
+    url.host_str().is_some_and(|host| {
+        host == domain
+            || host
+                .strip_suffix(domain)
+                .is_some_and(|sub| sub.ends_with('.'))
+    })
+}
+
+/// the non-empty segments of the url's path, ie. `["read", "vagabond-4"]`
+pub fn path_segments(url: &ReqUrl) -> Vec<&str> {
+    url.path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// returns the adapter with this [`SiteAdapter::name`]
+pub fn adapter_named(name: &str) -> Option<&'static dyn SiteAdapter> {
+    adapters().iter().copied().find(|a| a.name() == name)
+}
+
+/// A single page of a chapter, as listed by a [`SiteAdapter`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Page {
+    /// 0-based position of the page in the chapter
+    pub index: usize,
+    pub source: PageSource,
+}
+
+impl Page {
+    /// 1-based page number, used by `--pages` and for the page's file name
+    pub fn number(&self) -> usize {
+        self.index + 1
+    }
+}
+
+/// Where the bytes of a [`Page`] come from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PageSource {
+    /// a plain image url that can be fetched with reqwest.
+    Src(String),
+    /// the page has to be captured from the browser by the adapter itself.
+    Browser,
+}
+
+/// A chapter of a series, as listed by a [`SiteAdapter`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub number: f32,
+    /// absolute url of the chapter
+    pub url: String,
+}
+
+impl Chapter {
+    /// name of the chapter's folder inside the series folder
+    pub fn dir_name(&self) -> String {
+        format!("chapter-{}", self.number)
+    }
+}
+
+/// State of a browser session, shared by every chapter read in it.
+#[derive(Debug, Default)]
+pub struct SessionState {
+    /// sites that already ran their once-per-session setup
+    set_up: Mutex<HashSet<&'static str>>,
+}
+
+impl SessionState {
+    /// whether `site` still has to run its once-per-session setup, ie. picking a reading mode.
+    ///
+    /// only the first call for a site returns true.
+    pub fn needs_setup(&self, site: &'static str) -> bool {
+        self.set_up
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(site)
+    }
+}
+
+/// The browser a chapter is read in.
+///
+/// a new one is made for every chapter and passed to [`SiteAdapter::list_pages`] and
+/// [`SiteAdapter::fetch_page`], so downloads running at the same time never share a reader.
+#[derive(Debug)]
+pub struct Reader<'a> {
+    pub client: &'a Client,
+    pub session: &'a SessionState,
+    /// 0-based index of the page a one-page-at-a-time reader is currently showing
+    pub cursor: AtomicUsize,
+    /// whether the pages are `<img>` elements instead of canvases
+    pub images: AtomicBool,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(client: &'a Client, session: &'a SessionState) -> Self {
+        Reader {
+            client,
+            session,
+            cursor: AtomicUsize::new(0),
+            images: AtomicBool::new(false),
+        }
+    }
+}
+
+#[async_trait]
+pub trait SiteAdapter: Debug + Send + Sync {
+    /// name printed before each download
+    fn name(&self) -> &'static str;
+
+    /// whether the (already parsed) url belongs to this site
+    fn detect(&self, url: &ReqUrl) -> bool;
+
+    /// site specific checks on top of the ones every [`crate::Url`] goes through
+    fn validate(&self, _url: &ReqUrl) -> Result<(), ArgError> {
+        Ok(())
+    }
+
+    /// extract the title from the url
+    fn title(&self, url: &str) -> Option<String>;
+
+    /// whether the url is a series landing page instead of a single chapter
+    fn is_series(&self, _url: &str) -> bool {
+        false
+    }
+
+    /// name of the series a chapter url belongs to, for the chapter's metadata
+    fn series(&self, _url: &str) -> Option<String> {
+        None
+    }
+
+    /// ISO 639-1 code of the language the chapter is in
+    fn language(&self, _url: &str) -> Option<String> {
+        None
+    }
+
+    /// whether the site needs a browser to render its chapters.
+    ///
+    /// sites that don't are scraped from the html served for each url with
+    /// [`SiteAdapter::parse_chapters`] and [`SiteAdapter::parse_pages`],
+    /// and geckodriver isn't started when every url belongs to one.
+    fn needs_browser(&self) -> bool {
+        true
+    }
+
+    /// lists every chapter of the series page the client is currently on,
+    /// sorted by chapter number.
+    async fn list_chapters(&self, _client: &Client) -> Result<Vec<Chapter>> {
+        Err(eyre!("{} does not support series urls", self.name()))
+    }
+
+    /// lists every page of the chapter the reader is currently on.
+    ///
+    /// called once per chapter, right after navigating to it.
+    async fn list_pages(
+        &self,
+        _reader: &Reader<'_>,
+        _options: &DownloadOptions,
+    ) -> Result<Vec<Page>> {
+        Err(eyre!("{} is scraped without a browser", self.name()))
+    }
+
+    /// lists every chapter of a series from the html served for its `url`,
+    /// sorted by chapter number.
+    fn parse_chapters(&self, _html: &Html, _url: &str) -> Result<Vec<Chapter>> {
+        Err(eyre!("{} does not support series urls", self.name()))
+    }
+
+    /// lists every page of a chapter from the html served for its `url`.
+    fn parse_pages(&self, _html: &Html, _url: &str) -> Result<Vec<Page>> {
+        Err(eyre!("{} needs a browser", self.name()))
+    }
+
+    /// captures a page with a [`PageSource::Browser`] source.
+    ///
+    /// called in page order; [`PageSource::Src`] pages are fetched with reqwest instead.
+    async fn fetch_page(
+        &self,
+        _reader: &Reader<'_>,
+        _req_client: &ReqClient,
+        page: &Page,
+    ) -> Result<Vec<u8>, DownloadImageError> {
+        Err(DownloadImageError::MissingImgElement(
+            page.number().to_string(),
+        ))
+    }
+}
+
+/// collects the chapter links matched by `selector`, sorted by chapter number.
+///
+/// the number is parsed from the href; links without one are skipped.
+pub async fn list_chapter_links(client: &Client, selector: &str) -> Result<Vec<Chapter>> {
+    let links = client.find_all(Locator::Css(selector)).await?;
+    let mut chapters = Vec::with_capacity(links.len());
+
+    for link in links {
+        // the href property is always absolute, unlike the attribute
+        let Some(url) = link.prop("href").await? else {
+            continue;
+        };
+        if let Some(number) = parse_chapter_number(&url) {
+            chapters.push(Chapter { number, url });
+        }
+    }
+    sort_chapters(&mut chapters);
+
+    Ok(chapters)
+}
+
+/// sorts chapters by number, dropping repeated links to the same url.
+///
+/// chapters sharing a number but not a url, like two translations, are kept.
+pub fn sort_chapters(chapters: &mut Vec<Chapter>) {
+    chapters.sort_by(|a, b| a.number.total_cmp(&b.number));
+    let mut seen = HashSet::new();
+    chapters.retain(|chapter| seen.insert(chapter.url.clone()));
+}
+
+/// finds the chapter number in a chapter url or link text.
+///
+/// prefers a number following a word starting with `chapter`, `chap` or `di` (`di-1hua`),
+/// or mangagun's `gunchap`, else takes the last number.
+pub fn parse_chapter_number(s: &str) -> Option<f32> {
+    static CHAPTER_RE: OnceLock<Regex> = OnceLock::new();
+    static NUMBER_RE: OnceLock<Regex> = OnceLock::new();
+    let chapter_re = CHAPTER_RE.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:gun)?(?:chapter|chap|di)[-_ ]?(\d+(?:[.-]\d+)?)")
+            .expect("valid regex")
+    });
+    let number_re = NUMBER_RE.get_or_init(|| Regex::new(r"\d+(?:\.\d+)?").expect("valid regex"));
+
+    let number = match chapter_re.captures(s) {
+        Some(caps) => caps[1].replace('-', "."),
+        None => number_re.find_iter(s).last()?.as_str().to_string(),
+    };
+
+    number.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chapter_numbers() {
+        let cases = [
+            (
+                "https://mangareader.to/read/vagabond-4/ja/chapter-6",
+                Some(6.0),
+            ),
+            (
+                "https://mangagun.net/gunchap-999-shmg-one-piece-raw.html",
+                Some(999.0),
+            ),
+            (
+                "https://rawmanga.net/manga/zaziyoziyoranzu-the-jojolands/di-1hua",
+                Some(1.0),
+            ),
+            ("https://example.com/series/chapter-10-5", Some(10.5)),
+            ("https://example.com/series/12.5", Some(12.5)),
+            ("https://example.com/series/extra", None),
+            ("https://example.com/audi-5/di-3hua", Some(3.0)),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(parse_chapter_number(url), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn sorted_chapters_keep_shared_numbers() {
+        let chapter = |number, url: &str| Chapter {
+            number,
+            url: url.to_string(),
+        };
+        let mut chapters = vec![
+            chapter(2.0, "https://example.com/en/chapter-2"),
+            chapter(1.0, "https://example.com/en/chapter-1"),
+            chapter(2.0, "https://example.com/ja/chapter-2"),
+            chapter(1.0, "https://example.com/en/chapter-1"),
+        ];
+        sort_chapters(&mut chapters);
+
+        let urls: Vec<&str> = chapters.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/en/chapter-1",
+                "https://example.com/en/chapter-2",
+                "https://example.com/ja/chapter-2",
+            ]
+        );
+    }
+}
@@ -0,0 +1,30 @@
+//! Helpers shared by the unit tests.
+use std::{env, fs, path::PathBuf};
+
+/// A directory in the temp directory, removed when dropped
+/// so a failed assertion doesn't leave it behind.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("manga_dl-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create the temp dir");
+        TempDir(dir)
+    }
+
+    pub fn join(&self, path: &str) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// the path as the `&str` the download code passes folders around as
+    pub fn path_str(&self) -> String {
+        self.0.to_string_lossy().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use image::{ImageError, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use crate::{convert::encode_image, output::write_file_atomic};
+
+/// A border color given as `#rrggbb` or `r,g,b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BorderColor(pub [u8; 3]);
+
+impl FromStr for BorderColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("`{s}` is not a color, expected #rrggbb or r,g,b");
+        let s = s.trim();
+
+        if let Some(hex) = s.strip_prefix('#') {
+            let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+            if hex.len() != 6 {
+                return Err(invalid());
+            }
+            let [_, r, g, b] = value.to_be_bytes();
+            return Ok(BorderColor([r, g, b]));
+        }
+
+        let channels: Vec<u8> = s
+            .split(',')
+            .map(|c| c.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        match channels[..] {
+            [r, g, b] => Ok(BorderColor([r, g, b])),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// How page borders are trimmed with `--trim-borders`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrimOptions {
+    /// the border color, detected from the corners of every page if `None`
+    pub color: Option<BorderColor>,
+    /// how far (in percent) a pixel may stray from the border color and still count as border
+    pub tolerance: u8,
+}
+
+impl TrimOptions {
+    fn max_diff(&self) -> u8 {
+        (u16::from(self.tolerance.min(100)) * 255 / 100) as u8
+    }
+
+    fn matches(&self, px: &Rgba<u8>, color: [u8; 3]) -> bool {
+        let max_diff = self.max_diff();
+        px.0[..3]
+            .iter()
+            .zip(color)
+            .all(|(a, b)| a.abs_diff(b) <= max_diff)
+    }
+
+    /// the color most corners agree on, `None` if no two corners share one
+    fn detect_color(&self, img: &RgbaImage) -> Option<[u8; 3]> {
+        let (w, h) = img.dimensions();
+        let corners =
+            [(0, 0), (w - 1, 0), (0, h - 1), (w - 1, h - 1)].map(|(x, y)| img.get_pixel(x, y));
+
+        corners
+            .iter()
+            .map(|corner| {
+                let color = [corner.0[0], corner.0[1], corner.0[2]];
+                let agreeing = corners.iter().filter(|c| self.matches(c, color)).count();
+                (agreeing, color)
+            })
+            .max_by_key(|(agreeing, _)| *agreeing)
+            .filter(|(agreeing, _)| *agreeing >= 2)
+            .map(|(_, color)| color)
+    }
+
+    /// the (x, y, width, height) of the page without its borders,
+    /// `None` if there's nothing to trim or nothing but border
+    fn content_bounds(&self, img: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+        let (w, h) = img.dimensions();
+        if w == 0 || h == 0 {
+            return None;
+        }
+        let color = match self.color {
+            Some(BorderColor(color)) => color,
+            None => self.detect_color(img)?,
+        };
+        let is_border = |x: u32, y: u32| self.matches(img.get_pixel(x, y), color);
+        let row_is_border = |y: u32| (0..w).all(|x| is_border(x, y));
+        let col_is_border = |x: u32, top: u32, bottom: u32| (top..bottom).all(|y| is_border(x, y));
+
+        let top = (0..h).find(|&y| !row_is_border(y))?;
+        let bottom = (top..h).rev().find(|&y| !row_is_border(y))? + 1;
+        let left = (0..w).find(|&x| !col_is_border(x, top, bottom))?;
+        let right = (left..w).rev().find(|&x| !col_is_border(x, top, bottom))? + 1;
+
+        let bounds = (left, top, right - left, bottom - top);
+        (bounds != (0, 0, w, h)).then_some(bounds)
+    }
+
+    /// trims the borders of an encoded page, keeping its format.
+    ///
+    /// returns `None` when the page has no border to trim.
+    pub fn trim(&self, bytes: &[u8]) -> Result<Option<Vec<u8>>, ImageError> {
+        let format = image::guess_format(bytes)?;
+        let img = image::load_from_memory_with_format(bytes, format)?;
+
+        let Some((x, y, w, h)) = self.content_bounds(&img.to_rgba8()) else {
+            return Ok(None);
+        };
+        let trimmed = img.crop_imm(x, y, w, h);
+
+        encode_image(trimmed, format).map(Some)
+    }
+
+    /// trims every page in parallel, rewriting the ones that had a border.
+    ///
+    /// returns the new bytes of every rewritten page next to its path.
+    pub fn trim_pages(&self, pages: Vec<PathBuf>) -> Vec<(PathBuf, io::Result<Option<Vec<u8>>>)> {
+        pages
+            .into_par_iter()
+            .map(|path| {
+                let res = self.trim_file(&path);
+                (path, res)
+            })
+            .collect()
+    }
+
+    fn trim_file(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        let bytes = std::fs::read(path)?;
+        let Some(trimmed) = self.trim(&bytes).map_err(io::Error::other)? else {
+            return Ok(None);
+        };
+        write_file_atomic(path, |mut file| {
+            file.write_all(&trimmed)?;
+            Ok(file)
+        })?;
+
+        Ok(Some(trimmed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, ImageFormat};
+    use std::io::Cursor;
+
+    #[test]
+    fn trims_detected_border() -> Result<(), ImageError> {
+        // a dark page with a white 4x3 panel, slightly noisy border included
+        let mut img = RgbaImage::from_pixel(10, 10, Rgba([17, 17, 17, 255]));
+        img.put_pixel(0, 9, Rgba([20, 17, 15, 255]));
+        for x in 3..7 {
+            for y in 2..5 {
+                img.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+            }
+        }
+        let mut png = Cursor::new(Vec::new());
+        img.write_to(&mut png, ImageFormat::Png)?;
+
+        let options = TrimOptions {
+            color: None,
+            tolerance: 10,
+        };
+        let trimmed = options.trim(png.get_ref())?.expect("border trimmed");
+        assert_eq!(image::load_from_memory(&trimmed)?.dimensions(), (4, 3));
+        // already trimmed pages are left alone
+        assert_eq!(options.trim(&trimmed)?, None);
+
+        assert_eq!("#111111".parse(), Ok(BorderColor([17, 17, 17])));
+        assert_eq!("17, 17,17".parse(), Ok(BorderColor([17, 17, 17])));
+        assert!("#11111".parse::<BorderColor>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use color_eyre::owo_colors::OwoColorize;
+use reqwest::Url as ReqUrl;
+use std::str::FromStr;
+
+use crate::{
+    error::ArgError,
+    site::{find_adapter, SiteAdapter},
+    style_text,
+};
+
+/// A url to download, matched to the site it belongs to.
+#[derive(Debug, Clone)]
+pub struct Url {
+    pub url: String,
+    pub title: Option<String>,
+    pub site: &'static dyn SiteAdapter,
+    /// the url is a series landing page, not a single chapter
+    pub series: bool,
+}
+
+impl FromStr for Url {
+    type Err = ArgError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = Url::check_url(s)?;
+        let site = Url::is_site_supported(&url)?;
+
+        Ok(Url::with_site(url.to_string(), site))
+    }
+}
+
+impl Url {
+    /// a url of a site that's already known, without the checks [`Url::from_str`] does
+    pub fn with_site(url: String, site: &'static dyn SiteAdapter) -> Self {
+        Url {
+            title: site.title(&url),
+            series: site.is_series(&url),
+            site,
+            url,
+        }
+    }
+
+    fn is_site_supported(url: &ReqUrl) -> Result<&'static dyn SiteAdapter, ArgError> {
+        let site =
+            find_adapter(url).ok_or_else(|| ArgError::WebsiteNotSupported(url.to_string()))?;
+        site.validate(url)?;
+        Ok(site)
+    }
+
+    /// parses the url, assuming https when the scheme is left out.
+    ///
+    /// only the host is normalized (lowercase, punycode), paths and queries keep their case.
+    fn check_url(url: &str) -> Result<ReqUrl, ArgError> {
+        let url = url.trim();
+        let invalid = |reason: String, example: &str| ArgError::InvalidUrl {
+            url: url.to_string(),
+            reason: style_text!(reason),
+            example: style_text!(example),
+        };
+
+        if url.is_empty() {
+            return Err(invalid(
+                "Empty string".to_string(),
+                "mangareader.to/read/vagabond-4/ja/chapter-6",
+            ));
+        }
+
+        let with_scheme = match has_scheme(url) {
+            true => url.to_string(),
+            false => format!("https://{url}"),
+        };
+        let parsed = ReqUrl::parse(&with_scheme).map_err(|e| {
+            invalid(
+                format!("URL could not be parsed: {e}"),
+                "mangareader.to/read/vagabond-4/ja/chapter-6",
+            )
+        })?;
+
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(invalid(
+                format!("URL scheme {} is not http or https", parsed.scheme()),
+                "https://mangareader.to/vagabond-4",
+            ));
+        }
+
+        if !parsed.has_host() {
+            return Err(invalid(
+                "URL has no host".to_string(),
+                "*.com | *.to | *.net",
+            ));
+        }
+        // ip addresses have no domain and are let through
+        if let Some(domain) = parsed.domain() {
+            if !domain.trim_end_matches('.').contains('.') {
+                return Err(invalid(
+                    format!("URL host {domain} is missing a top-level domain"),
+                    "*.com | *.to | *.net",
+                ));
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// whether the url starts with `<scheme>://`; a `://` further in, ie. in the query, doesn't count
+fn has_scheme(url: &str) -> bool {
+    url.split_once("://").is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(url: &str) -> String {
+        match Url::from_str(url) {
+            Err(ArgError::InvalidUrl { reason, .. }) => reason,
+            other => panic!("{url}: expected InvalidUrl, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn supported_urls() -> Result<(), ArgError> {
+        let mangareader = Url::from_str("mangareader.to/read/Vagabond-4/ja/chapter-6")?;
+        assert_eq!(mangareader.site.name(), "MangaReader");
+        assert_eq!(
+            mangareader.url,
+            "https://mangareader.to/read/Vagabond-4/ja/chapter-6"
+        );
+        assert!(!mangareader.series);
+
+        let mangagun = Url::from_str("https://MangaGun.net/gunchap-999-shmg-one-piece-raw.html")?;
+        assert_eq!(mangagun.site.name(), "MangaGun");
+        assert_eq!(
+            mangagun.url,
+            "https://mangagun.net/gunchap-999-shmg-one-piece-raw.html"
+        );
+
+        let rawmanga = Url::from_str("http://www.rawmanga.net/manga/one-piece?sort=ASC")?;
+        assert_eq!(rawmanga.site.name(), "RawManga");
+        assert_eq!(
+            rawmanga.url,
+            "http://www.rawmanga.net/manga/one-piece?sort=ASC"
+        );
+        assert!(rawmanga.series);
+        assert_eq!(rawmanga.title.as_deref(), Some("one-piece"));
+
+        let query = Url::from_str("mangareader.to/read/vagabond-4/ja/chapter-6?ref=https://x.com")?;
+        assert_eq!(
+            query.url,
+            "https://mangareader.to/read/vagabond-4/ja/chapter-6?ref=https://x.com"
+        );
+        assert_eq!(query.title.as_deref(), Some("vagabond-4-ja-chapter-6"));
+        assert_eq!(query.site.language(&query.url).as_deref(), Some("ja"));
+
+        let series = Url::from_str("mangagun.net/one-piece-raw.html#chapters")?;
+        assert!(series.series);
+        assert_eq!(series.title.as_deref(), Some("one-piece-raw.html"));
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_urls() {
+        for url in [
+            "example.com/?q=mangagun",
+            "https://example.com/mangareader.to/vagabond-4",
+            "https://notrawmanga.net/manga/one-piece",
+        ] {
+            assert!(
+                matches!(Url::from_str(url), Err(ArgError::WebsiteNotSupported(_))),
+                "{url}"
+            );
+        }
+
+        assert!(reason("").contains("Empty string"));
+        assert!(reason("ftp://mangareader.to/vagabond-4").contains("scheme ftp"));
+        assert!(reason("https://mangareader/vagabond-4").contains("top-level domain"));
+        assert!(reason("https://mangareader.to:99999/x").contains("could not be parsed"));
+        assert!(reason("mangareader.to/read/vagabond-4").contains("/read/<series>"));
+        assert!(reason("mangareader.to").contains("missing a series"));
+        assert!(reason("mangagun.net/manga/one-piece").contains("single .html page"));
+        assert!(reason("rawmanga.net/one-piece").contains("/manga/<series>"));
+    }
+}
@@ -0,0 +1,469 @@
+//! Offline stand-ins for the sites and the browser, so the site tests never touch the network.
+//!
+//! [`Fixtures`] serves the saved pages in `tests/fixtures/<site>` and generated page images
+//! from a local http server; [`FakeWebDriver`] answers the WebDriver commands the adapters use
+//! by running their css selectors against the same fixtures with scraper.
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::Cursor,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use base64::prelude::*;
+use image::{ImageFormat, Rgb, RgbImage};
+use manga_dl::{options::DownloadOptions, retry::RetryPolicy, site::adapter_named, Url};
+use scraper::{ElementRef, Html, Selector};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// the key WebDriver uses for element references
+const ELEMENT_KEY: &str = "element-6066-11e4-a52e-4f735466cecf";
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: Value) -> Self {
+        Response {
+            status,
+            content_type: "application/json; charset=utf-8",
+            body: json!({ "value": value }).to_string().into_bytes(),
+        }
+    }
+
+    fn not_found() -> Self {
+        Response {
+            status: 404,
+            content_type: "text/plain",
+            body: b"not found".to_vec(),
+        }
+    }
+}
+
+/// serves every request with `handler` on a free local port, one request per connection
+async fn serve(handler: Arc<dyn Fn(Request) -> Response + Send + Sync>) -> SocketAddr {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .expect("bind a local port");
+    let addr = listener.local_addr().expect("local address");
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let _ = respond(stream, handler).await;
+            });
+        }
+    });
+
+    addr
+}
+
+async fn respond(
+    mut stream: TcpStream,
+    handler: Arc<dyn Fn(Request) -> Response + Send + Sync>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let head_end = loop {
+        let mut chunk = [0; 4096];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).into_owned();
+    let mut lines = head.lines();
+    let mut start = lines.next().unwrap_or_default().split(' ');
+    let method = start.next().unwrap_or_default().to_string();
+    let path = start.next().unwrap_or_default().to_string();
+    let length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let mut body = buf[head_end..].to_vec();
+    while body.len() < length {
+        let mut chunk = [0; 4096];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    let res = handler(Request { method, path, body });
+    let head = format!(
+        "HTTP/1.1 {} X\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        res.status,
+        res.content_type,
+        res.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&res.body).await?;
+    stream.shutdown().await
+}
+
+/// a distinct png for every page number, so a page landing in the wrong file is noticed
+pub fn page_image(number: u32) -> Vec<u8> {
+    let img = RgbImage::from_pixel(100, 100 + number, Rgb([number as u8 * 40, 0, 0]));
+    let mut bytes = Cursor::new(Vec::new());
+    img.write_to(&mut bytes, ImageFormat::Png)
+        .expect("encode a png");
+    bytes.into_inner()
+}
+
+/// path -> (content type, body)
+type Routes = Arc<Mutex<HashMap<String, (&'static str, Vec<u8>)>>>;
+
+/// The saved pages of a site, served from a local http server.
+#[derive(Clone)]
+pub struct Fixtures {
+    /// `http://127.0.0.1:<port>`, replaces `{{base}}` in the saved pages
+    pub base: String,
+    routes: Routes,
+}
+
+impl Fixtures {
+    /// serves `/images/<n>.png` for every page number in `images`
+    pub async fn start(images: impl IntoIterator<Item = u32>) -> Self {
+        let routes = Routes::default();
+        let served = routes.clone();
+        let addr = serve(Arc::new(move |req: Request| {
+            let path = req.path.split('?').next().unwrap_or_default();
+            match served.lock().unwrap().get(path) {
+                Some((content_type, body)) if req.method == "GET" => Response {
+                    status: 200,
+                    content_type,
+                    body: body.clone(),
+                },
+                _ => Response::not_found(),
+            }
+        }))
+        .await;
+
+        let fixtures = Fixtures {
+            base: format!("http://{addr}"),
+            routes,
+        };
+        for number in images {
+            fixtures.image(number);
+        }
+        fixtures
+    }
+
+    /// serves `tests/fixtures/<file>` at `path`
+    pub fn page(&self, path: &str, file: &str) -> &Self {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(file);
+        let html = fs::read_to_string(&file)
+            .unwrap_or_else(|e| panic!("{}: {e}", file.display()))
+            .replace("{{base}}", &self.base);
+        self.routes
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), ("text/html; charset=utf-8", html.into()));
+        self
+    }
+
+    /// serves [`page_image`] at `/images/<number>.png`
+    pub fn image(&self, number: u32) -> &Self {
+        self.routes.lock().unwrap().insert(
+            format!("/images/{number}.png"),
+            ("image/png", page_image(number)),
+        );
+        self
+    }
+
+    pub fn remove(&self, path: &str) {
+        self.routes.lock().unwrap().remove(path);
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base)
+    }
+
+    /// the body served at an absolute url of this server
+    fn get(&self, url: &str) -> Option<Vec<u8>> {
+        let path = url.strip_prefix(&self.base)?;
+        let path = path.split('?').next()?;
+        self.routes
+            .lock()
+            .unwrap()
+            .get(path)
+            .map(|(_, body)| body.clone())
+    }
+}
+
+/// Rewrites the page source for scripts the fake browser should react to,
+/// returns the script's result. Scripts without a handler do nothing and return `null`.
+pub type ScriptHandler = dyn Fn(&str, &mut String) -> Option<Value> + Send + Sync;
+
+/// What the fake browser is showing.
+struct Tab {
+    url: String,
+    source: String,
+    /// bumped on every navigation so references to old elements go stale
+    generation: u32,
+}
+
+/// A WebDriver server whose "browser" only parses the fixture pages.
+///
+/// elements are referenced by their position in document order, so they survive
+/// script handlers that only change attributes, but not navigation.
+pub struct FakeWebDriver {
+    pub url: String,
+}
+
+impl FakeWebDriver {
+    pub async fn start(fixtures: Fixtures, scripts: Arc<ScriptHandler>) -> Self {
+        let tab = Arc::new(Mutex::new(Tab {
+            url: "about:blank".to_string(),
+            source: String::new(),
+            generation: 0,
+        }));
+        let addr = serve(Arc::new(move |req: Request| {
+            let body: Value = serde_json::from_slice(&req.body).unwrap_or(Value::Null);
+            let mut tab = tab.lock().unwrap();
+            match command(
+                &mut tab,
+                &fixtures,
+                &*scripts,
+                &req.method,
+                &req.path,
+                &body,
+            ) {
+                Ok(value) => Response::json(200, value),
+                Err((status, error, message)) => Response::json(
+                    status,
+                    json!({ "error": error, "message": message, "stacktrace": "" }),
+                ),
+            }
+        }))
+        .await;
+
+        FakeWebDriver {
+            url: format!("http://{addr}"),
+        }
+    }
+}
+
+type CommandError = (u16, &'static str, String);
+
+fn no_such_element(selector: &str) -> CommandError {
+    (
+        404,
+        "no such element",
+        format!("no element matches {selector}"),
+    )
+}
+
+fn command(
+    tab: &mut Tab,
+    fixtures: &Fixtures,
+    scripts: &ScriptHandler,
+    method: &str,
+    path: &str,
+    body: &Value,
+) -> Result<Value, CommandError> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let doc = Html::parse_document(&tab.source);
+    let elements: Vec<ElementRef> = doc
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .collect();
+    let reference = |elm: &ElementRef| {
+        let position = elements
+            .iter()
+            .position(|e| e.id() == elm.id())
+            .expect("element of the document");
+        json!({ ELEMENT_KEY: format!("{}-{position}", tab.generation) })
+    };
+    let element = |id: &str| -> Result<ElementRef, CommandError> {
+        id.split_once('-')
+            .filter(|(generation, _)| *generation == tab.generation.to_string())
+            .and_then(|(_, position)| elements.get(position.parse::<usize>().ok()?))
+            .copied()
+            .ok_or((404, "stale element reference", id.to_string()))
+    };
+    let selector = || -> Result<(String, Selector), CommandError> {
+        let css = body["value"].as_str().unwrap_or_default().to_string();
+        let selector =
+            Selector::parse(&css).map_err(|e| (400, "invalid selector", format!("{css}: {e}")))?;
+        Ok((css, selector))
+    };
+    let resolve = |href: &str| {
+        reqwest::Url::parse(&tab.url)
+            .and_then(|base| base.join(href))
+            .map_or_else(|_| href.to_string(), String::from)
+    };
+
+    match (method, segments.as_slice()) {
+        ("POST", ["session"]) => Ok(json!({ "sessionId": "fake", "capabilities": {} })),
+        ("DELETE", ["session", _]) => Ok(Value::Null),
+        ("GET", ["session", _, "url"]) => Ok(json!(tab.url)),
+        ("POST", ["session", _, "url"]) => {
+            let url = body["url"].as_str().unwrap_or_default().to_string();
+            let source = fixtures.get(&url).unwrap_or_default();
+            tab.source = String::from_utf8_lossy(&source).into_owned();
+            tab.url = url;
+            tab.generation += 1;
+            Ok(Value::Null)
+        }
+        ("POST", ["session", _, "element"]) => {
+            let (css, selector) = selector()?;
+            let found = doc.select(&selector).next();
+            found
+                .map(|elm| reference(&elm))
+                .ok_or_else(|| no_such_element(&css))
+        }
+        ("POST", ["session", _, "elements"]) => {
+            let (_, selector) = selector()?;
+            Ok(doc.select(&selector).map(|e| reference(&e)).collect())
+        }
+        ("POST", ["session", _, "element", id, "element"]) => {
+            let (css, selector) = selector()?;
+            let found = element(id)?.select(&selector).next();
+            found
+                .map(|elm| reference(&elm))
+                .ok_or_else(|| no_such_element(&css))
+        }
+        ("POST", ["session", _, "element", id, "elements"]) => {
+            let (_, selector) = selector()?;
+            Ok(element(id)?
+                .select(&selector)
+                .map(|e| reference(&e))
+                .collect())
+        }
+        ("GET", ["session", _, "element", id, "attribute", name]) => {
+            Ok(json!(element(id)?.value().attr(name)))
+        }
+        ("GET", ["session", _, "element", id, "property", name]) => {
+            let elm = element(id)?;
+            Ok(match *name {
+                "innerHTML" => json!(elm.inner_html()),
+                "outerHTML" => json!(elm.html()),
+                // like a browser, url properties are absolute
+                "href" | "src" => json!(elm.value().attr(name).map(resolve)),
+                _ => json!(elm.value().attr(name)),
+            })
+        }
+        ("GET", ["session", _, "element", id, "text"]) => {
+            Ok(json!(element(id)?.text().collect::<String>().trim()))
+        }
+        ("POST", ["session", _, "element", id, "click"]) => element(id).map(|_| Value::Null),
+        ("GET", ["session", _, "element", id, "screenshot"]) => {
+            // the screenshot of an image is the image itself
+            let elm = element(id)?;
+            let src = elm.value().attr("src").map(resolve).unwrap_or_default();
+            let bytes = fixtures.get(&src).ok_or((
+                404,
+                "unknown error",
+                format!("nothing to capture at {src}"),
+            ))?;
+            Ok(json!(BASE64_STANDARD.encode(bytes)))
+        }
+        ("POST", ["session", _, "execute", "sync"]) => {
+            let script = body["script"].as_str().unwrap_or_default();
+            Ok(scripts(script, &mut tab.source).unwrap_or(Value::Null))
+        }
+        ("GET", ["session", _, "window", "handles"]) => Ok(json!(["main"])),
+        ("GET", ["session", _, "window"]) => Ok(json!("main")),
+        ("POST", ["session", _, "window"]) => Ok(Value::Null),
+        ("DELETE", ["session", _, "window"]) => Ok(json!([])),
+        _ => Err((
+            404,
+            "unknown command",
+            format!("the fake webdriver can't {method} {path}"),
+        )),
+    }
+}
+
+/// scripts that only hide elements or dismiss popups, which the fake browser ignores
+pub fn no_scripts() -> Arc<ScriptHandler> {
+    Arc::new(|_, _| None)
+}
+
+/// A download root in the temp directory, removed when dropped.
+pub struct TempRoot(pub PathBuf);
+
+impl TempRoot {
+    pub fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("manga_dl-test-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create the download root");
+        TempRoot(dir)
+    }
+
+    pub fn join(&self, path: &str) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempRoot {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// options for a quick offline run into `root`
+pub fn options(root: &TempRoot, webdriver: Option<&FakeWebDriver>) -> DownloadOptions {
+    DownloadOptions {
+        root: root.0.display().to_string(),
+        retry: RetryPolicy {
+            max_attempts: 2,
+            base_delay: std::time::Duration::ZERO,
+            jitter: false,
+            ..Default::default()
+        },
+        error_log: Some(root.join("errors.log")),
+        webdriver: webdriver.map(|driver| driver.url.clone()),
+        ..Default::default()
+    }
+}
+
+/// a url of the fixture server handled by the site named `site`
+pub fn site_url(site: &str, url: String) -> Url {
+    let site = adapter_named(site).unwrap_or_else(|| panic!("no adapter named {site}"));
+    Url::with_site(url, site)
+}
+
+/// the page files written into a chapter folder, sorted by page number
+pub fn page_files(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut files: Vec<(String, Vec<u8>)> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("{}: {e}", dir.display()))
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?.to_string();
+            let number = name.split('.').next()?;
+            number.parse::<u32>().ok()?;
+            Some((name, fs::read(&path).ok()?))
+        })
+        .collect();
+    files.sort_by_key(|(name, _)| name.split('.').next().unwrap().parse::<u32>().unwrap());
+    files
+}
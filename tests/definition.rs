@@ -0,0 +1,57 @@
+//! Sites from a definition file, read in the fake WebDriver like the default `needs_browser`.
+mod common;
+
+use std::fs;
+
+use common::{
+    no_scripts, options, page_files, page_image, site_url, FakeWebDriver, Fixtures, TempRoot,
+};
+use manga_dl::{
+    definition::load_site_definitions,
+    site::{register_adapters, SiteAdapter},
+    RunStatus,
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn relative_srcs() {
+    let fixtures = Fixtures::start(1..=3).await;
+    fixtures.page("/manga/jojolands/di-1hua", "rawmanga/di-1hua.html");
+    let driver = FakeWebDriver::start(fixtures.clone(), no_scripts()).await;
+    let root = TempRoot::new("definition-relative-srcs");
+
+    let sites = root.join("sites");
+    fs::create_dir_all(&sites).unwrap();
+    fs::write(
+        sites.join("mirror.toml"),
+        r#"
+            name = "Mirror"
+            host = "127.0.0.1"
+            url_pattern = "^/manga/"
+            title_pattern = "/manga/(?<title>.+)"
+            page_selector = "div.page-chapter img"
+        "#,
+    )
+    .unwrap();
+    let definitions = load_site_definitions(&sites).unwrap();
+    register_adapters(
+        definitions
+            .into_iter()
+            .map(|site| Box::new(site) as Box<dyn SiteAdapter>)
+            .collect(),
+    );
+
+    let url = site_url("Mirror", fixtures.url("/manga/jojolands/di-1hua"));
+    let report = manga_dl::download(&[url], &options(&root, Some(&driver)))
+        .await
+        .unwrap();
+
+    assert_eq!(report.status, RunStatus::Success);
+    assert_eq!(
+        page_files(&root.join("jojolands-di-1hua")),
+        [
+            ("1.png".to_string(), page_image(1)),
+            ("2.png".to_string(), page_image(2)),
+            ("3.png".to_string(), page_image(3)),
+        ]
+    );
+}
@@ -0,0 +1,75 @@
+//! mangagun needs a browser: its pages are captured from the fake WebDriver.
+mod common;
+
+use common::{
+    no_scripts, options, page_files, page_image, site_url, FakeWebDriver, Fixtures, TempRoot,
+};
+use manga_dl::RunStatus;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn series() {
+    let fixtures = Fixtures::start(1..=5).await;
+    fixtures
+        .page("/one-piece-raw.html", "mangagun/series.html")
+        .page(
+            "/gunchap-1-shmg-one-piece-raw.html",
+            "mangagun/gunchap-1.html",
+        )
+        .page(
+            "/gunchap-2-shmg-one-piece-raw.html",
+            "mangagun/gunchap-2.html",
+        );
+    let driver = FakeWebDriver::start(fixtures.clone(), no_scripts()).await;
+    let root = TempRoot::new("mangagun-series");
+
+    let url = site_url("MangaGun", fixtures.url("/one-piece-raw.html"));
+    let report = manga_dl::download(&[url], &options(&root, Some(&driver)))
+        .await
+        .unwrap();
+
+    assert_eq!(report.status, RunStatus::Success);
+    assert_eq!(
+        page_files(&root.join("one-piece-raw.html/chapter-1")),
+        [
+            ("1.png".to_string(), page_image(1)),
+            ("2.png".to_string(), page_image(2)),
+            ("3.png".to_string(), page_image(3)),
+        ]
+    );
+    assert_eq!(
+        page_files(&root.join("one-piece-raw.html/chapter-2")),
+        [
+            ("1.png".to_string(), page_image(4)),
+            ("2.png".to_string(), page_image(5)),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stuck_reader() {
+    // none of the images are served, so every page the browser tries fails
+    let fixtures = Fixtures::start([]).await;
+    fixtures.page(
+        "/gunchap-3-shmg-one-piece-raw.html",
+        "mangagun/gunchap-3.html",
+    );
+    let driver = FakeWebDriver::start(fixtures.clone(), no_scripts()).await;
+    let root = TempRoot::new("mangagun-stuck-reader");
+
+    let url = site_url(
+        "MangaGun",
+        fixtures.url("/gunchap-3-shmg-one-piece-raw.html"),
+    );
+    let report = manga_dl::download(&[url], &options(&root, Some(&driver)))
+        .await
+        .unwrap();
+
+    assert_eq!(report.status, RunStatus::TotalFailure);
+    // the pages after the reader got stuck are failures too, not left out
+    let failures = &report.urls[0].chapters[0].failures;
+    assert_eq!(failures.len(), 6);
+    assert_eq!(
+        (failures[5].index, failures[5].variant, failures[5].attempts),
+        (5, "ReaderStuck", 0)
+    );
+}
@@ -0,0 +1,126 @@
+//! mangareader shows one panel at a time, so the fake browser has to follow `hozNextImage()`.
+mod common;
+
+use std::sync::Arc;
+
+use common::{
+    options, page_files, page_image, site_url, FakeWebDriver, Fixtures, ScriptHandler, TempRoot,
+};
+use manga_dl::RunStatus;
+use serde_json::Value;
+
+const ACTIVE: &str = r#"<div class="ds-item active">"#;
+const ITEM: &str = r#"<div class="ds-item">"#;
+
+/// moves the `active` class to the next panel, like the reader's own script
+fn reader_scripts() -> Arc<ScriptHandler> {
+    Arc::new(|script, source| {
+        if !script.contains("hozNextImage()") {
+            return None;
+        }
+        let current = source.find(ACTIVE)?;
+        let next = current + ACTIVE.len() + source[current + ACTIVE.len()..].find(ITEM)?;
+        source.replace_range(next..next + ITEM.len(), ACTIVE);
+        source.replace_range(current..current + ACTIVE.len(), ITEM);
+        Some(Value::Null)
+    })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn series() {
+    let fixtures = Fixtures::start(1..=5).await;
+    fixtures
+        .page("/vagabond-4", "mangareader/series.html")
+        .page(
+            "/read/vagabond-4/ja/chapter-1",
+            "mangareader/chapter-1.html",
+        )
+        .page(
+            "/read/vagabond-4/ja/chapter-2",
+            "mangareader/chapter-2.html",
+        );
+    let driver = FakeWebDriver::start(fixtures.clone(), reader_scripts()).await;
+    let root = TempRoot::new("mangareader-series");
+
+    let url = site_url("MangaReader", fixtures.url("/vagabond-4"));
+    let report = manga_dl::download(&[url], &options(&root, Some(&driver)))
+        .await
+        .unwrap();
+
+    assert_eq!(report.status, RunStatus::Success);
+    // the last panel of the reader links to the next chapter and isn't a page
+    assert_eq!(
+        page_files(&root.join("vagabond-4/chapter-1")),
+        [
+            ("1.png".to_string(), page_image(1)),
+            ("2.png".to_string(), page_image(2)),
+            ("3.png".to_string(), page_image(3)),
+        ]
+    );
+    assert_eq!(
+        page_files(&root.join("vagabond-4/chapter-2")),
+        [
+            ("1.png".to_string(), page_image(4)),
+            ("2.png".to_string(), page_image(5)),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_runs() {
+    let fixtures = Fixtures::start(1..=3).await;
+    fixtures.page(
+        "/read/vagabond-4/ja/chapter-1",
+        "mangareader/chapter-1.html",
+    );
+    let (driver_a, driver_b) = tokio::join!(
+        FakeWebDriver::start(fixtures.clone(), reader_scripts()),
+        FakeWebDriver::start(fixtures.clone(), reader_scripts()),
+    );
+    let (root_a, root_b) = (
+        TempRoot::new("mangareader-concurrent-a"),
+        TempRoot::new("mangareader-concurrent-b"),
+    );
+    let (options_a, options_b) = (
+        options(&root_a, Some(&driver_a)),
+        options(&root_b, Some(&driver_b)),
+    );
+
+    // each run walks its own reader, neither moves the other's cursor
+    let url = site_url("MangaReader", fixtures.url("/read/vagabond-4/ja/chapter-1"));
+    let urls = [url];
+    let (a, b) = tokio::join!(
+        manga_dl::download(&urls, &options_a),
+        manga_dl::download(&urls, &options_b),
+    );
+
+    for (report, root) in [(a.unwrap(), &root_a), (b.unwrap(), &root_b)] {
+        assert_eq!(report.status, RunStatus::Success);
+        assert_eq!(
+            page_files(&root.join("vagabond-4-ja-chapter-1")),
+            [
+                ("1.png".to_string(), page_image(1)),
+                ("2.png".to_string(), page_image(2)),
+                ("3.png".to_string(), page_image(3)),
+            ]
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn empty_page_counter() {
+    let fixtures = Fixtures::start([]).await;
+    fixtures.page(
+        "/read/vagabond-4/ja/chapter-3",
+        "mangareader/chapter-empty.html",
+    );
+    let driver = FakeWebDriver::start(fixtures.clone(), reader_scripts()).await;
+    let root = TempRoot::new("mangareader-empty-counter");
+
+    let url = site_url("MangaReader", fixtures.url("/read/vagabond-4/ja/chapter-3"));
+    let report = manga_dl::download(&[url], &options(&root, Some(&driver)))
+        .await
+        .unwrap();
+
+    assert_eq!(report.status, RunStatus::TotalFailure);
+}
@@ -0,0 +1,295 @@
+//! rawmanga is scraped over plain http, so only the fixture server is needed.
+mod common;
+
+use std::{
+    fs,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use common::{options, page_files, page_image, site_url, Fixtures, TempRoot};
+use manga_dl::{
+    naming::NameTemplate, options::PageRanges, output::OutputFormat, ProgressCallback,
+    ProgressEvent, RunStatus,
+};
+
+async fn jojolands() -> Fixtures {
+    let fixtures = Fixtures::start(1..=5).await;
+    fixtures
+        .page("/manga/jojolands", "rawmanga/series.html")
+        .page("/manga/jojolands/di-1hua", "rawmanga/di-1hua.html")
+        .page("/manga/jojolands/di-2hua", "rawmanga/di-2hua.html");
+    fixtures
+}
+
+#[tokio::test]
+async fn series() {
+    let fixtures = jojolands().await;
+    let root = TempRoot::new("rawmanga-series");
+    let written = Arc::new(AtomicUsize::new(0));
+    let counter = written.clone();
+    let options = manga_dl::DownloadOptions {
+        on_progress: Some(ProgressCallback::new(move |event| {
+            if let ProgressEvent::PageWritten { .. } = event {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        })),
+        ..options(&root, None)
+    };
+
+    let url = site_url("RawManga", fixtures.url("/manga/jojolands"));
+    let report = manga_dl::download(&[url], &options).await.unwrap();
+
+    assert_eq!(report.status, RunStatus::Success);
+    assert_eq!(written.load(Ordering::Relaxed), 5);
+    let chapter = root.join("jojolands/chapter-1");
+    assert_eq!(
+        page_files(&chapter),
+        [
+            ("1.png".to_string(), page_image(1)),
+            ("2.png".to_string(), page_image(2)),
+            ("3.png".to_string(), page_image(3)),
+        ]
+    );
+    assert!(chapter.join("ComicInfo.xml").is_file());
+    assert_eq!(
+        page_files(&root.join("jojolands/chapter-2")),
+        [
+            ("1.png".to_string(), page_image(4)),
+            ("2.png".to_string(), page_image(5)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn name_template() {
+    let fixtures = jojolands().await;
+    let root = TempRoot::new("rawmanga-name");
+    let options = manga_dl::DownloadOptions {
+        name: Some(NameTemplate::from_str("{site}/{series}/ch {chapter:03}").unwrap()),
+        ..options(&root, None)
+    };
+
+    let url = site_url("RawManga", fixtures.url("/manga/jojolands"));
+    let report = manga_dl::download(&[url], &options).await.unwrap();
+
+    assert_eq!(report.status, RunStatus::Success);
+    assert_eq!(page_files(&root.join("RawManga/jojolands/ch 001")).len(), 3);
+    assert_eq!(page_files(&root.join("RawManga/jojolands/ch 002")).len(), 2);
+}
+
+#[tokio::test]
+async fn page_ranges() {
+    let fixtures = jojolands().await;
+    let root = TempRoot::new("rawmanga-pages");
+    let options = manga_dl::DownloadOptions {
+        pages: Some(PageRanges::from_str("2-").unwrap()),
+        ..options(&root, None)
+    };
+
+    let url = site_url("RawManga", fixtures.url("/manga/jojolands/di-1hua"));
+    let report = manga_dl::download(&[url], &options).await.unwrap();
+
+    assert_eq!(report.status, RunStatus::Success);
+    let chapter = &report.urls[0].chapters[0];
+    assert_eq!((chapter.pages_expected, chapter.pages_written), (2, 2));
+    let files: Vec<String> = page_files(&root.join("jojolands_di-1hua"))
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(files, ["2.png", "3.png"]);
+    let comic_info = fs::read_to_string(root.join("jojolands_di-1hua/ComicInfo.xml")).unwrap();
+    assert!(comic_info.contains("<PageCount>2</PageCount>"));
+}
+
+#[tokio::test]
+async fn chapters_without_pages_fail() {
+    let fixtures = jojolands().await;
+    // the series page has no page images, like a chapter whose selector broke
+    fixtures.page("/manga/jojolands/di-3hua", "rawmanga/series.html");
+    let root = TempRoot::new("rawmanga-empty");
+    let options = manga_dl::DownloadOptions {
+        output: OutputFormat::Cbz,
+        ..options(&root, None)
+    };
+
+    let url = site_url("RawManga", fixtures.url("/manga/jojolands/di-3hua"));
+    let report = manga_dl::download(&[url], &options).await.unwrap();
+
+    assert_eq!(report.status, RunStatus::TotalFailure);
+    assert!(report.urls[0].error.is_some());
+    assert!(!root.join("jojolands_di-3hua.cbz").exists());
+}
+
+#[tokio::test]
+async fn resume_twice() {
+    let fixtures = jojolands().await;
+    let root = TempRoot::new("rawmanga-resume");
+    let options = manga_dl::DownloadOptions {
+        resume: true,
+        ..options(&root, None)
+    };
+
+    // the second run has no page left to fetch
+    for _ in 0..2 {
+        let url = site_url("RawManga", fixtures.url("/manga/jojolands/di-1hua"));
+        let report = manga_dl::download(&[url], &options).await.unwrap();
+        assert_eq!(report.status, RunStatus::Success);
+    }
+    assert_eq!(page_files(&root.join("jojolands_di-1hua")).len(), 3);
+}
+
+#[tokio::test]
+async fn write_errors_only_fail_their_page() {
+    let fixtures = jojolands().await;
+    let root = TempRoot::new("rawmanga-write");
+    let chapter = root.join("jojolands_di-1hua");
+    // a folder in the way of a page makes renaming the written page fail
+    std::fs::create_dir_all(chapter.join("2.png/taken")).unwrap();
+
+    let url = site_url("RawManga", fixtures.url("/manga/jojolands/di-1hua"));
+    let report = manga_dl::download(&[url], &options(&root, None))
+        .await
+        .unwrap();
+
+    assert_eq!(report.status, RunStatus::PartialFailure);
+    let failures = &report.urls[0].chapters[0].failures;
+    assert_eq!(failures.len(), 1);
+    assert_eq!((failures[0].index, failures[0].variant), (1, "Io"));
+    assert_eq!(report.urls[0].chapters[0].pages_written, 2);
+    assert!(!chapter.join("2.png.part").exists());
+}
+
+#[tokio::test]
+async fn error_pages_are_not_saved_as_images() {
+    let fixtures = jojolands().await;
+    // a 200 response with html where the image should be
+    fixtures.page("/images/2.png", "rawmanga/series.html");
+    let root = TempRoot::new("rawmanga-invalid");
+
+    let url = site_url("RawManga", fixtures.url("/manga/jojolands/di-1hua"));
+    let report = manga_dl::download(&[url], &options(&root, None))
+        .await
+        .unwrap();
+
+    assert_eq!(report.status, RunStatus::PartialFailure);
+    let failures = &report.urls[0].chapters[0].failures;
+    assert_eq!(failures.len(), 1);
+    assert_eq!(
+        (failures[0].index, failures[0].variant, failures[0].attempts),
+        (1, "InvalidImage", 2)
+    );
+    let files: Vec<String> = page_files(&root.join("jojolands_di-1hua"))
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(files, ["1.png", "3.png"]);
+}
+
+#[tokio::test]
+async fn stopping_a_run_leaves_the_others_alone() {
+    let fixtures = jojolands().await;
+    let (root_a, root_b) = (
+        TempRoot::new("rawmanga-stop-a"),
+        TempRoot::new("rawmanga-stop-b"),
+    );
+    let (stopped, running) = (options(&root_a, None), options(&root_b, None));
+    stopped.shutdown.request();
+
+    let url = site_url("RawManga", fixtures.url("/manga/jojolands/di-1hua"));
+    let urls = [url];
+    let (a, b) = tokio::join!(
+        manga_dl::download(&urls, &stopped),
+        manga_dl::download(&urls, &running),
+    );
+
+    assert_eq!(a.unwrap().status, RunStatus::Interrupted);
+    assert_eq!(b.unwrap().status, RunStatus::Success);
+}
+
+#[tokio::test]
+async fn log_errors_dont_stop_the_chapter() {
+    let fixtures = jojolands().await;
+    fixtures.remove("/images/1.png");
+    let root = TempRoot::new("rawmanga-log-errors");
+    // a folder can't be appended to
+    let options = manga_dl::DownloadOptions {
+        error_log: Some(root.0.clone()),
+        ..options(&root, None)
+    };
+
+    let url = site_url("RawManga", fixtures.url("/manga/jojolands/di-1hua"));
+    let report = manga_dl::download(&[url], &options).await.unwrap();
+
+    assert_eq!(report.status, RunStatus::PartialFailure);
+    assert_eq!(report.urls[0].chapters[0].failures.len(), 1);
+    assert_eq!(report.urls[0].chapters[0].pages_written, 2);
+}
+
+#[tokio::test]
+async fn pages_written_later_leave_the_log() {
+    let fixtures = jojolands().await;
+    fixtures.remove("/images/2.png");
+    let root = TempRoot::new("rawmanga-log-resume");
+    let options = manga_dl::DownloadOptions {
+        resume: true,
+        output: OutputFormat::Cbz,
+        ..options(&root, None)
+    };
+    let log = root.join("errors.log");
+
+    let url = site_url("RawManga", fixtures.url("/manga/jojolands/di-1hua"));
+    let report = manga_dl::download(std::slice::from_ref(&url), &options)
+        .await
+        .unwrap();
+    assert_eq!(report.status, RunStatus::PartialFailure);
+    assert_eq!(manga_dl::failure_log::read(&log).unwrap().len(), 1);
+
+    // the rerun finishes and packs the chapter, so there's nothing left to retry
+    fixtures.image(2);
+    let report = manga_dl::download(&[url], &options).await.unwrap();
+    assert_eq!(report.status, RunStatus::Success);
+    assert!(root.join("jojolands_di-1hua.cbz").exists());
+    assert!(!log.exists());
+}
+
+#[tokio::test]
+async fn failed_pages_are_retried_from_the_log() {
+    let fixtures = jojolands().await;
+    fixtures.remove("/images/2.png");
+    let root = TempRoot::new("rawmanga-retry");
+    let options = options(&root, None);
+    let log = root.join("errors.log");
+
+    let url = site_url("RawManga", fixtures.url("/manga/jojolands/di-1hua"));
+    let report = manga_dl::download(&[url], &options).await.unwrap();
+
+    assert_eq!(report.status, RunStatus::PartialFailure);
+    let failures = &report.urls[0].chapters[0].failures;
+    assert_eq!(failures.len(), 1);
+    assert_eq!((failures[0].index, failures[0].variant), (1, "Status"));
+    let logged = manga_dl::failure_log::read(&log).unwrap();
+    assert_eq!(logged.len(), 1);
+    assert_eq!(logged[0].index, 1);
+
+    // still missing: the entry stays
+    let report = manga_dl::retry(&log, &options).await.unwrap();
+    assert_eq!(report.status, RunStatus::PartialFailure);
+    assert_eq!(manga_dl::failure_log::read(&log).unwrap().len(), 1);
+
+    fixtures.image(2);
+    let report = manga_dl::retry(&log, &options).await.unwrap();
+    assert_eq!(report.status, RunStatus::Success);
+    assert!(!log.exists());
+    assert_eq!(
+        page_files(&root.join("jojolands_di-1hua")),
+        [
+            ("1.png".to_string(), page_image(1)),
+            ("2.png".to_string(), page_image(2)),
+            ("3.png".to_string(), page_image(3)),
+        ]
+    );
+}